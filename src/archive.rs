@@ -0,0 +1,149 @@
+use crate::progress::Progress;
+use flate2::read::MultiGzDecoder;
+use std::error::Error;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek};
+use std::ops::ControlFlow;
+use std::path::Path;
+
+// Called with the display name of each accepted entry (e.g. `archive.zip!/inner/file.csv`) and its
+// contents. Breaking stops the walk without reading the rest of the archive.
+pub type EntryVisitor<'a> = dyn FnMut(&str, &mut dyn Read) -> Result<ControlFlow<()>, Box<dyn Error>> + 'a;
+
+type Walk = Result<ControlFlow<()>, Box<dyn Error>>;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ArchiveKind {
+    Zip,
+    Gzip,
+    Tar,
+}
+
+fn archive_kind(name: &str) -> Option<ArchiveKind> {
+    let name = name.to_lowercase();
+    if name.ends_with(".zip") {
+        Some(ArchiveKind::Zip)
+    } else if name.ends_with(".gz") || name.ends_with(".tgz") {
+        Some(ArchiveKind::Gzip)
+    } else if name.ends_with(".tar") {
+        Some(ArchiveKind::Tar)
+    } else {
+        None
+    }
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    archive_kind(&path.to_string_lossy()).is_some()
+}
+
+// Extensions to offer in file pickers alongside the plain formats
+pub const ARCHIVE_EXTENSIONS: [&str; 4] = ["zip", "gz", "tgz", "tar"];
+
+// Name of the file inside a gzip stream: `data.csv.gz` -> `data.csv`, `data.tgz` -> `data.tar`
+fn gzip_inner_name(name: &str) -> String {
+    let file_name = name.rsplit(['/', '\\', '!']).next().unwrap_or(name);
+    let lower = file_name.to_lowercase();
+    if lower.ends_with(".tgz") {
+        format!("{}.tar", &file_name[..file_name.len() - 4])
+    } else if lower.ends_with(".gz") {
+        file_name[..file_name.len() - 3].to_string()
+    } else {
+        file_name.to_string()
+    }
+}
+
+// Visits `path` itself if it is a plain file, or every entry accepted by `accept` if it is a
+// zip, gzip, tar or tar.gz archive. A plain file was picked by the caller, so `accept` only
+// filters archive members. Nested archives are descended into as well.
+// The bytes read from `path` are counted in `progress`.
+pub fn visit_file(path: &Path, progress: &Progress, accept: &dyn Fn(&str) -> bool, visit: &mut EntryVisitor) -> Result<(), Box<dyn Error>> {
+    let name = path.display().to_string();
+    let mut file = progress.reader(File::open(path)?);
+    let walk = match archive_kind(&name) {
+        Some(ArchiveKind::Zip) => visit_zip(&name, file, accept, visit),
+        Some(kind) => visit_stream(kind, &name, &mut file, accept, visit),
+        None => visit(&name, &mut file),
+    };
+    // Whether the visitor stopped early is its own business
+    walk.map(|_| ())
+}
+
+fn visit_entry(display: &str, inner_name: &str, reader: &mut dyn Read, accept: &dyn Fn(&str) -> bool, visit: &mut EntryVisitor) -> Walk {
+    match archive_kind(inner_name) {
+        // Zip needs random access, so a nested zip is buffered in memory first
+        Some(ArchiveKind::Zip) => visit_zip(display, buffer_zip(display, reader)?, accept, visit),
+        Some(kind) => visit_stream(kind, display, reader, accept, visit),
+        None if accept(inner_name) => visit(display, reader),
+        None => Ok(ControlFlow::Continue(())),
+    }
+}
+
+// Largest nested zip that will be read into memory
+const MAX_NESTED_ZIP_BYTES: u64 = 512 * 1024 * 1024;
+
+fn buffer_zip(display: &str, reader: &mut dyn Read) -> Result<Cursor<Vec<u8>>, Box<dyn Error>> {
+    let mut buffer = Vec::new();
+    reader.take(MAX_NESTED_ZIP_BYTES + 1).read_to_end(&mut buffer)?;
+    if buffer.len() as u64 > MAX_NESTED_ZIP_BYTES {
+        return Err(format!("{} is larger than {} MB; extract it before processing", display, MAX_NESTED_ZIP_BYTES / (1024 * 1024)).into());
+    }
+    Ok(Cursor::new(buffer))
+}
+
+fn visit_zip<R: Read + Seek>(display: &str, reader: R, accept: &dyn Fn(&str) -> bool, visit: &mut EntryVisitor) -> Walk {
+    let mut zip = zip::ZipArchive::new(reader)?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let inner_name = entry.name().to_string();
+        let entry_display = format!("{}!/{}", display, inner_name);
+        if visit_entry(&entry_display, &inner_name, &mut entry, accept, visit)?.is_break() {
+            return Ok(ControlFlow::Break(()));
+        }
+    }
+    Ok(ControlFlow::Continue(()))
+}
+
+fn visit_stream(kind: ArchiveKind, display: &str, reader: &mut dyn Read, accept: &dyn Fn(&str) -> bool, visit: &mut EntryVisitor) -> Walk {
+    match kind {
+        ArchiveKind::Gzip => {
+            let inner_name = gzip_inner_name(display);
+            let mut decoder = MultiGzDecoder::new(reader);
+            // Report tar.gz entries as `archive.tar.gz!/inner` rather than going through the tar name
+            if archive_kind(&inner_name) == Some(ArchiveKind::Tar) {
+                visit_tar(display, &mut decoder, accept, visit)
+            } else {
+                let entry_display = format!("{}!/{}", display, inner_name);
+                visit_entry(&entry_display, &inner_name, &mut decoder, accept, visit)
+            }
+        }
+        ArchiveKind::Tar => visit_tar(display, reader, accept, visit),
+        ArchiveKind::Zip => visit_zip(display, buffer_zip(display, reader)?, accept, visit),
+    }
+}
+
+fn visit_tar(display: &str, reader: &mut dyn Read, accept: &dyn Fn(&str) -> bool, visit: &mut EntryVisitor) -> Walk {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let inner_name = entry.path()?.to_string_lossy().into_owned();
+        let entry_display = format!("{}!/{}", display, inner_name);
+        if visit_entry(&entry_display, &inner_name, &mut entry, accept, visit)?.is_break() {
+            return Ok(ControlFlow::Break(()));
+        }
+    }
+    Ok(ControlFlow::Continue(()))
+}
+
+// Accepts names whose extension is one of `extensions` (case-insensitive)
+pub fn has_extension(name: &str, extensions: &[&str]) -> bool {
+    Path::new(name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| extensions.iter().any(|e| e.eq_ignore_ascii_case(&ext)))
+}
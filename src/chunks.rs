@@ -0,0 +1,168 @@
+use crate::dialect::{Dialect, Escape};
+
+// A byte range of a file that starts and ends on a record boundary
+pub struct Chunk {
+    pub start: usize,
+    pub end: usize,
+    // Number of the first record in the chunk, counted the way `formats::read_records` counts them
+    pub first_record: usize,
+    pub records: usize,
+}
+
+// Where a CSV reader is within the current record
+#[derive(Clone, Copy, PartialEq)]
+enum CsvState {
+    FieldStart,
+    Unquoted,
+    Quoted,
+    EscapeInQuoted,
+    QuoteInQuoted,
+}
+
+// Splits `data` into chunks of roughly `target_size` bytes that can be parsed independently.
+// Delimited data, read with `dialect`, is cut only outside quoted fields, and blank lines are not
+// counted as records (as the csv reader skips them). Other data is cut at any line break.
+pub fn split_records(data: &[u8], target_size: usize, dialect: Option<&Dialect>) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut chunk_start = 0;
+    let mut first_record = 1;
+    let mut records = 0;
+    let mut state = CsvState::FieldStart;
+    let mut line_has_content = false;
+
+    for (pos, &byte) in data.iter().enumerate() {
+        let record_end = match dialect {
+            None => byte == b'\n',
+            Some(dialect) => {
+                let quote = dialect.quote;
+                let (next, end) = match (state, byte) {
+                    (CsvState::Quoted, b'\\') if dialect.escape == Escape::Backslash => (CsvState::EscapeInQuoted, false),
+                    (CsvState::EscapeInQuoted, _) => (CsvState::Quoted, false),
+                    (CsvState::Quoted, b) if b == quote => (CsvState::QuoteInQuoted, false),
+                    (CsvState::Quoted, _) => (CsvState::Quoted, false),
+                    (CsvState::FieldStart, b) | (CsvState::QuoteInQuoted, b) if b == quote => (CsvState::Quoted, false),
+                    (_, b'\n') => (CsvState::FieldStart, true),
+                    (_, b) if b == dialect.delimiter => (CsvState::FieldStart, false),
+                    _ => (CsvState::Unquoted, false),
+                };
+                state = next;
+                end
+            }
+        };
+
+        if !record_end {
+            line_has_content |= byte != b'\r';
+            continue;
+        }
+        if dialect.is_none() || line_has_content {
+            records += 1;
+        }
+        line_has_content = false;
+
+        if pos + 1 - chunk_start >= target_size && pos + 1 < data.len() {
+            chunks.push(Chunk { start: chunk_start, end: pos + 1, first_record, records: records + 1 - first_record });
+            chunk_start = pos + 1;
+            first_record = records + 1;
+        }
+    }
+
+    if chunk_start < data.len() || chunks.is_empty() {
+        // A last record without a line break is counted too
+        let records = records + 1 - first_record + usize::from(line_has_content);
+        chunks.push(Chunk { start: chunk_start, end: data.len(), first_record, records });
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: Dialect = Dialect { delimiter: b',', quote: b'"', escape: Escape::Doubled, has_header: false };
+    const BACKSLASH: Dialect = Dialect { escape: Escape::Backslash, ..CSV };
+
+    // Each chunk as text, with its first record number and record count
+    fn split<'a>(data: &'a str, target_size: usize, dialect: Option<&Dialect>) -> Vec<(&'a str, usize, usize)> {
+        split_records(data.as_bytes(), target_size, dialect)
+            .into_iter()
+            .map(|chunk| (&data[chunk.start..chunk.end], chunk.first_record, chunk.records))
+            .collect()
+    }
+
+    // What the csv reader makes of each chunk must add up to what it makes of the whole input
+    fn assert_parses_like_whole(data: &str, dialect: &Dialect) {
+        let parse = |bytes: &[u8]| -> Vec<csv::StringRecord> {
+            let mut builder = dialect.reader_builder();
+            builder.has_headers(false).flexible(true);
+            builder.from_reader(bytes).records().map(Result::unwrap).collect()
+        };
+        let whole = parse(data.as_bytes());
+        for target_size in 1..=data.len() {
+            let chunks = split_records(data.as_bytes(), target_size, Some(dialect));
+            let mut records = Vec::new();
+            for chunk in &chunks {
+                let parsed = parse(&data.as_bytes()[chunk.start..chunk.end]);
+                assert_eq!(parsed.len(), chunk.records, "target size {}", target_size);
+                assert_eq!(chunk.first_record, records.len() + 1, "target size {}", target_size);
+                records.extend(parsed);
+            }
+            assert_eq!(records, whole, "target size {}", target_size);
+        }
+    }
+
+    #[test]
+    fn cuts_after_each_record() {
+        assert_eq!(split("a,b\nc,d\ne,f\n", 1, Some(&CSV)), vec![("a,b\n", 1, 1), ("c,d\n", 2, 1), ("e,f\n", 3, 1)]);
+        assert_eq!(split("a,b\nc,d\ne,f\n", 100, Some(&CSV)), vec![("a,b\nc,d\ne,f\n", 1, 3)]);
+    }
+
+    #[test]
+    fn does_not_cut_at_a_quoted_line_break() {
+        let data = "a,\"x\ny\"\nb,c\n";
+        assert_eq!(split(data, 1, Some(&CSV)), vec![("a,\"x\ny\"\n", 1, 1), ("b,c\n", 2, 1)]);
+        assert_parses_like_whole(data, &CSV);
+    }
+
+    #[test]
+    fn doubled_quotes_stay_inside_the_field() {
+        let data = "a,\"say \"\"hi\n\"\"\"\nb\n";
+        assert_eq!(split(data, 1, Some(&CSV)), vec![("a,\"say \"\"hi\n\"\"\"\n", 1, 1), ("b\n", 2, 1)]);
+        assert_parses_like_whole(data, &CSV);
+    }
+
+    #[test]
+    fn backslash_escapes_a_quote_only_in_that_dialect() {
+        let data = "a,\"x\\\"\ny\"\nb\n";
+        assert_eq!(split(data, 1, Some(&BACKSLASH)), vec![("a,\"x\\\"\ny\"\n", 1, 1), ("b\n", 2, 1)]);
+        assert_parses_like_whole(data, &BACKSLASH);
+        // With doubled quotes the backslash is an ordinary character, and the quote ends the field
+        assert_eq!(split("a,\"x\\\"\nb\n", 1, Some(&CSV)), vec![("a,\"x\\\"\n", 1, 1), ("b\n", 2, 1)]);
+    }
+
+    #[test]
+    fn crlf_line_breaks_and_blank_lines() {
+        let data = "a,b\r\nc,d\r\n\r\ne,f\r\n";
+        assert_eq!(split(data, 1, Some(&CSV)), vec![("a,b\r\n", 1, 1), ("c,d\r\n", 2, 1), ("\r\n", 3, 0), ("e,f\r\n", 3, 1)]);
+        assert_parses_like_whole(data, &CSV);
+    }
+
+    #[test]
+    fn a_boundary_inside_a_quoted_field_moves_to_the_record_end() {
+        let data = "\"abcdefgh\nij\",x\nk,l\n";
+        assert_eq!(split(data, 4, Some(&CSV)), vec![("\"abcdefgh\nij\",x\n", 1, 1), ("k,l\n", 2, 1)]);
+        assert_parses_like_whole(data, &CSV);
+    }
+
+    #[test]
+    fn counts_a_last_record_without_a_line_break() {
+        assert_eq!(split("a\nb", 1, Some(&CSV)), vec![("a\n", 1, 1), ("b", 2, 1)]);
+        assert_eq!(split("a\nb", 1, None), vec![("a\n", 1, 1), ("b", 2, 1)]);
+        assert_eq!(split("a\n\"b\nc\"", 100, Some(&CSV)), vec![("a\n\"b\nc\"", 1, 2)]);
+        assert_parses_like_whole("a,\"x\ny\"\n\r\nb,\"\"\"c\"", &CSV);
+    }
+
+    #[test]
+    fn text_is_cut_at_any_line_break() {
+        assert_eq!(split("a \"b\nc\n\nd\n", 1, None), vec![("a \"b\n", 1, 1), ("c\n", 2, 1), ("\n", 3, 1), ("d\n", 4, 1)]);
+    }
+}
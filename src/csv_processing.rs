@@ -1,29 +1,109 @@
 use eframe::egui;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Instant;
 use egui::{RichText, Stroke, Rounding};
 use rfd::FileDialog;
+use crate::archive;
+use crate::dialect::{self, DialectOptions};
+use crate::encoding::{self, InputEncoding};
+use crate::events::{Event, EventKind, EventSender, Summary, TabId, TabStatus};
+use crate::history::{InputFile, JobDetails, JobRecord, JobSettings, OutputFile};
+use crate::pipeline::{CsvFilters, DirOutput, Operation};
+use crate::presets::PresetPicker;
+use crate::records::{self, BadRecords, CsvReading};
+use crate::preview::PreviewPane;
+use crate::progress::{self, Progress};
+use serde::{Deserialize, Serialize};
 
-pub struct CsvProcessingTab {
-    states: String,
-    email_domains: String,
+// What can be saved as a preset and is restored on launch
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvProcessingSettings {
+    pub states: String,
+    pub email_domains: String,
+    pub encoding: InputEncoding,
+    pub dialect: DialectOptions,
+    // Keep going past malformed rows, listing them in bad_records.csv in the output folder
+    pub lenient: bool,
+    // Empty for the current folder
+    pub output_dir: PathBuf,
 }
 
-impl CsvProcessingTab {
-    pub fn new() -> Self {
+impl Default for CsvProcessingSettings {
+    fn default() -> Self {
         Self {
             states: "NY,OH,PA,WA,AK".to_string(),
             email_domains: "@gmail.com".to_string(),
+            encoding: InputEncoding::Auto,
+            dialect: DialectOptions::default(),
+            lenient: false,
+            output_dir: PathBuf::new(),
+        }
+    }
+}
+
+impl CsvProcessingSettings {
+    fn states_and_domains(&self) -> (Vec<String>, Vec<String>) {
+        let states = self.states.split(',').map(|s| s.trim().to_string()).collect();
+        let email_domains = self.email_domains.split(',').map(|s| s.trim().to_string()).collect();
+        (states, email_domains)
+    }
+}
+
+// Rows each file would send to each state output, counted without writing anything
+struct DryRun {
+    states: Vec<String>,
+    files: Vec<(String, Result<Vec<u64>, String>)>,
+    // Rows each output would hold: a run writes every input to the same outputs, including the
+    // rows of a file before it failed
+    totals: Vec<u64>,
+    // Counted in lenient mode
+    bad_records: Option<u64>,
+    done: bool,
+}
+
+pub struct CsvProcessingTab {
+    settings: CsvProcessingSettings,
+    presets: PresetPicker,
+    preview: PreviewPane,
+    dry_run: Option<Arc<Mutex<DryRun>>>,
+    progress: Arc<Progress>,
+    status: TabStatus,
+}
+
+impl CsvProcessingTab {
+    pub fn new(settings: CsvProcessingSettings) -> Self {
+        Self {
+            settings,
+            presets: PresetPicker::new("csv_processing"),
+            preview: PreviewPane::new(),
+            dry_run: None,
+            progress: Arc::new(Progress::new()),
+            status: TabStatus::new(),
         }
     }
 
-    pub fn ui(&mut self, ui: &mut egui::Ui, selected_files: &mut Vec<PathBuf>, processing_status: &mut String, tx: &Sender<String>) {
+    pub fn handle_event(&mut self, event: EventKind) {
+        self.status.apply(event);
+    }
+
+    pub fn settings(&self) -> &CsvProcessingSettings {
+        &self.settings
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, selected_files: &mut Vec<PathBuf>, tx: &Sender<Event>) {
+        self.presets.ui(ui, &mut self.settings);
+        ui.add_space(10.0);
+
         // File selection UI
         ui.horizontal(|ui| {
             if ui.button(RichText::new("📁 Select CSV Files").size(18.0)).clicked() {
                 if let Some(files) = FileDialog::new()
                     .add_filter("CSV", &["csv"])
+                    .add_filter("Archives", &archive::ARCHIVE_EXTENSIONS)
                     .set_directory("/")
                     .pick_files()
                 {
@@ -32,6 +112,7 @@ impl CsvProcessingTab {
             }
             ui.label(RichText::new(format!("Selected files: {}", selected_files.len())).size(16.0));
         });
+        self.preview.ui(ui, "csv_processing_preview", selected_files, self.settings.encoding, &self.settings.dialect.header_if_chosen());
 
         ui.add_space(10.0);
 
@@ -44,12 +125,32 @@ impl CsvProcessingTab {
                 ui.add_space(10.0);
                 ui.horizontal(|ui| {
                     ui.label(RichText::new("States:").size(16.0));
-                    ui.add(egui::TextEdit::singleline(&mut self.states).hint_text("NY, OH, PA, WA, AK"));
+                    ui.add(egui::TextEdit::singleline(&mut self.settings.states).hint_text("NY, OH, PA, WA, AK"));
                 });
                 ui.add_space(10.0);
                 ui.horizontal(|ui| {
                     ui.label(RichText::new("Email domains:").size(16.0));
-                    ui.add(egui::TextEdit::singleline(&mut self.email_domains).hint_text("@gmail.com, @yahoo.com"));
+                    ui.add(egui::TextEdit::singleline(&mut self.settings.email_domains).hint_text("@gmail.com, @yahoo.com"));
+                });
+                ui.add_space(10.0);
+                encoding::encoding_selector(ui, "csv_processing_encoding", &mut self.settings.encoding);
+                dialect::dialect_selector(ui, "csv_processing_dialect", &mut self.settings.dialect, false);
+                ui.checkbox(&mut self.settings.lenient, "Lenient: skip or repair malformed rows and list them in bad_records.csv");
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Output Folder").clicked() {
+                        if let Some(dir) = FileDialog::new().pick_folder() {
+                            self.settings.output_dir = dir;
+                        }
+                    }
+                    if self.settings.output_dir.as_os_str().is_empty() {
+                        ui.label("Output files: output_<state>.csv in the current folder");
+                    } else {
+                        ui.label(format!("Output folder: {}", self.settings.output_dir.display()));
+                        if ui.small_button("Use current folder").clicked() {
+                            self.settings.output_dir = PathBuf::new();
+                        }
+                    }
                 });
                 ui.add_space(10.0);
             });
@@ -57,32 +158,202 @@ impl CsvProcessingTab {
         ui.add_space(20.0);
 
         // Process files button
-        if ui.add_sized([ui.available_width(), 40.0], egui::Button::new(RichText::new("🚀 Process Files").size(20.0))).clicked() {
-            let files = selected_files.clone();
-            let tx = tx.clone();
-            let states: Vec<String> = self.states.split(',').map(|s| s.trim().to_string()).collect();
-            let email_domains: Vec<String> = self.email_domains.split(',').map(|s| s.trim().to_string()).collect();
-            thread::spawn(move || {
-                for file in files {
-                    if let Err(e) = crate::process_csv_file(&file, &states, &email_domains) {
-                        tx.send(format!("Error processing {}: {}", file.display(), e))
-                            .unwrap();
-                    } else {
-                        tx.send(format!("Processed: {}", file.display())).unwrap();
-                    }
-                }
-                tx.send("All files processed".to_string()).unwrap();
-            });
+        let running = self.progress.is_running();
+        if ui.add_enabled_ui(!running, |ui| ui.add_sized([ui.available_width(), 40.0], egui::Button::new(RichText::new("🚀 Process Files").size(20.0)))).inner.clicked() {
+            self.start(selected_files.clone(), tx);
+        }
+        if ui.add_enabled(!running && !selected_files.is_empty(), egui::Button::new("🔍 Dry run")).clicked() {
+            self.start_dry_run(selected_files.clone());
+        }
+        if let Some(dry_run) = &self.dry_run {
+            dry_run_ui(ui, &dry_run.lock().unwrap(), &self.settings.output_dir);
         }
 
         ui.add_space(10.0);
+        progress::progress_panel(ui, &self.progress);
 
         // Display processing status
-        if !processing_status.is_empty() {
-            ui.horizontal(|ui| {
-                ui.label(RichText::new("Status:").strong());
-                ui.label(&*processing_status.clone());
-            });
+        self.status.ui(ui);
+    }
+
+    pub fn pipeline_step(&self, selected_files: &[PathBuf]) -> Operation {
+        let split = |text: &str| text.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+        Operation::CsvProcessing {
+            inputs: selected_files.to_vec(),
+            encoding: self.settings.encoding,
+            dialect: self.settings.dialect,
+            lenient: self.settings.lenient,
+            filters: CsvFilters { states: split(&self.settings.states), email_domains: split(&self.settings.email_domains) },
+            outputs: DirOutput { dir: self.settings.output_dir.clone() },
         }
     }
-}
\ No newline at end of file
+
+    pub fn load_step(&mut self, operation: &Operation, selected_files: &mut Vec<PathBuf>) {
+        let Operation::CsvProcessing { inputs, encoding, dialect, lenient, filters, outputs } = operation else {
+            return;
+        };
+        self.settings.states = filters.states.join(",");
+        self.settings.email_domains = filters.email_domains.join(",");
+        self.settings.encoding = *encoding;
+        self.settings.dialect = *dialect;
+        self.settings.lenient = *lenient;
+        self.settings.output_dir = outputs.dir.clone();
+        *selected_files = inputs.clone();
+    }
+
+    // Runs a job from the history again, on the same files
+    pub fn rerun(&mut self, job: &JobRecord, selected_files: &mut Vec<PathBuf>, tx: &Sender<Event>) {
+        let JobSettings::CsvProcessing { states, email_domains, encoding, dialect, lenient, output_dir } = &job.settings else {
+            return;
+        };
+        if self.progress.is_running() {
+            self.status.set("Files are still being processed");
+            return;
+        }
+        self.settings.states = states.join(",");
+        self.settings.email_domains = email_domains.join(",");
+        self.settings.encoding = *encoding;
+        self.settings.dialect = *dialect;
+        self.settings.lenient = *lenient;
+        self.settings.output_dir = output_dir.clone();
+        *selected_files = job.inputs.iter().map(|input| input.path.clone()).collect();
+        self.start(selected_files.clone(), tx);
+    }
+
+    fn start(&mut self, files: Vec<PathBuf>, tx: &Sender<Event>) {
+        let events = EventSender::new(TabId::CsvProcessing, tx);
+        let progress = self.progress.clone();
+        progress.start(&files);
+        let settings = self.settings.clone();
+        thread::spawn(move || process_files(&files, &settings, &events, &progress));
+    }
+
+    // Goes through the files as processing would, but only counts the rows of each state
+    fn start_dry_run(&mut self, files: Vec<PathBuf>) {
+        let progress = self.progress.clone();
+        progress.start(&files);
+        let (states, email_domains) = self.settings.states_and_domains();
+        let mut reading = CsvReading {
+            encoding: self.settings.encoding,
+            dialect: self.settings.dialect.header_if_chosen(),
+            bad_records: self.settings.lenient.then(BadRecords::counting),
+            header: None,
+        };
+        let dry_run = Arc::new(Mutex::new(DryRun { states: states.clone(), files: Vec::new(), totals: vec![0; states.len()], bad_records: None, done: false }));
+        self.dry_run = Some(dry_run.clone());
+        thread::spawn(move || {
+            for file in &files {
+                progress.set_current_file(&file.display().to_string());
+                let mut rows = vec![0; states.len()];
+                let result = crate::split_by_state(file, &states, &email_domains, &mut reading, &progress, &mut |state_index, _| {
+                    rows[state_index] += 1;
+                    Ok(())
+                });
+                let mut dry_run = dry_run.lock().unwrap();
+                for (total, count) in dry_run.totals.iter_mut().zip(&rows) {
+                    *total += count;
+                }
+                let counted = result.map(|_| rows).map_err(|e| e.to_string());
+                dry_run.files.push((file.display().to_string(), counted));
+                dry_run.bad_records = reading.bad_records.as_ref().map(BadRecords::count);
+                drop(dry_run);
+                progress.file_done();
+            }
+            progress.finish();
+            dry_run.lock().unwrap().done = true;
+        });
+    }
+}
+
+fn dry_run_ui(ui: &mut egui::Ui, dry_run: &DryRun, output_dir: &Path) {
+    ui.add_space(10.0);
+    let title = if dry_run.done { "Dry run: nothing was written" } else { "Dry run: counting rows..." };
+    ui.label(RichText::new(title).strong());
+    egui::ScrollArea::horizontal().id_source("csv_processing_dry_run").show(ui, |ui| {
+        egui::Grid::new("csv_processing_dry_run_rows").striped(true).show(ui, |ui| {
+            ui.label(RichText::new("File").strong());
+            for state in &dry_run.states {
+                let output = crate::state_output_path(output_dir, state);
+                ui.label(RichText::new(state).strong()).on_hover_text(output.display().to_string());
+            }
+            ui.end_row();
+            for (file, counted) in &dry_run.files {
+                ui.label(file);
+                match counted {
+                    Ok(rows) => {
+                        for count in rows {
+                            ui.label(count.to_string());
+                        }
+                    }
+                    Err(e) => {
+                        ui.colored_label(egui::Color32::RED, e);
+                    }
+                }
+                ui.end_row();
+            }
+            ui.label(RichText::new("Rows in each output").strong());
+            for total in &dry_run.totals {
+                ui.label(RichText::new(total.to_string()).strong());
+            }
+            ui.end_row();
+        });
+    });
+    if let Some(count) = dry_run.bad_records {
+        ui.label(format!("Bad records that would be listed in {}: {}", output_dir.join(records::BAD_RECORDS_FILE).display(), count));
+    }
+}
+
+// Splits `files` into one output file per state, for the tab or a pipeline step. `progress` has
+// already been started by the caller.
+pub fn process_files(files: &[PathBuf], settings: &CsvProcessingSettings, events: &EventSender, progress: &Progress) {
+    let (states, email_domains) = settings.states_and_domains();
+    let CsvProcessingSettings { encoding, dialect, lenient, output_dir, .. } = settings.clone();
+    let started = Instant::now();
+    let mut errors = 0;
+    events.started(format!("Processing {} files...", files.len()));
+    let inputs = files.iter().map(|file| InputFile::hash(file)).collect();
+    let mut reading = CsvReading::new(encoding, dialect.header_if_chosen(), lenient, Some(&output_dir.join(records::BAD_RECORDS_FILE)), events);
+    // Rows of each state's output, as written by this run
+    let mut written = vec![0; states.len()];
+    match crate::create_state_writers(&output_dir, &states) {
+        Ok(mut writers) => {
+            for file in files {
+                progress.set_current_file(&file.display().to_string());
+                match crate::process_csv_file(file, &states, &email_domains, &mut reading, &mut writers, &mut written, progress) {
+                    Ok(processed) => {
+                        let sources: Vec<String> = processed
+                            .iter()
+                            .map(|(name, used_encoding, used_dialect)| format!("{} ({}, {})", name, used_encoding.label(), used_dialect.describe()))
+                            .collect();
+                        events.file_done(file.display().to_string(), sources.join(", "));
+                    }
+                    Err(e) => {
+                        errors += 1;
+                        events.file_error(&file.display().to_string(), format!("Error processing {}: {}", file.display(), e));
+                    }
+                }
+                progress.file_done();
+            }
+        }
+        Err(e) => {
+            errors += 1;
+            events.error(format!("Could not create the outputs in {}: {}", output_dir.display(), e));
+        }
+    }
+    progress.finish();
+    let mut outputs: Vec<OutputFile> = states
+        .iter()
+        .zip(written)
+        .map(|(state, rows)| OutputFile { path: crate::state_output_path(&output_dir, state), rows })
+        .filter(|output| output.path.exists())
+        .collect();
+    outputs.extend(reading.finish(events));
+    let settings = JobSettings::CsvProcessing { states, email_domains, encoding, dialect, lenient, output_dir };
+    events.finished(Summary {
+        message: "All files processed".to_string(),
+        files: files.len(),
+        errors,
+        elapsed: started.elapsed(),
+        details: JobDetails { settings, inputs, outputs, rows: progress.snapshot().rows },
+    });
+}
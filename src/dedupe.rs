@@ -0,0 +1,684 @@
+use csv::{StringRecord, WriterBuilder};
+use eframe::egui;
+use regex::Regex;
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+use egui::RichText;
+use rfd::FileDialog;
+use serde::{Deserialize, Serialize};
+use crate::archive;
+use crate::dialect::{self, DialectOptions};
+use crate::encoding::{self, InputEncoding};
+use crate::events::{Event, EventKind, EventSender, Summary, TabId, TabStatus};
+use crate::formats;
+use crate::history::{InputFile, JobDetails, JobRecord, JobSettings, OutputFile};
+use crate::matching;
+use crate::pipeline::{DedupeKeys, FileOutput, Operation};
+use crate::presets::PresetPicker;
+use crate::preview::PreviewPane;
+use crate::progress::{self, Progress};
+use crate::records::{self, CsvReading};
+
+// What makes two records the same contact
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum DedupeKey {
+    #[default]
+    Email,
+    Phone,
+    // Records sharing either are merged, and so are records linked through others
+    EmailOrPhone,
+    EmailAndPhone,
+}
+
+impl DedupeKey {
+    pub const ALL: [DedupeKey; 4] = [DedupeKey::Email, DedupeKey::Phone, DedupeKey::EmailOrPhone, DedupeKey::EmailAndPhone];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DedupeKey::Email => "Email",
+            DedupeKey::Phone => "Phone",
+            DedupeKey::EmailOrPhone => "Email or phone",
+            DedupeKey::EmailAndPhone => "Email and phone",
+        }
+    }
+}
+
+// Which record is written for a cluster of duplicates
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum MergeStrategy {
+    #[default]
+    KeepFirst,
+    // The record with the most non-empty fields, the first of them on a tie
+    MostComplete,
+    // The first record, with its empty fields filled from the later ones
+    MergeFields,
+}
+
+impl MergeStrategy {
+    pub const ALL: [MergeStrategy; 3] = [MergeStrategy::KeepFirst, MergeStrategy::MostComplete, MergeStrategy::MergeFields];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MergeStrategy::KeepFirst => "Keep first",
+            MergeStrategy::MostComplete => "Keep most complete",
+            MergeStrategy::MergeFields => "Merge non-empty fields",
+        }
+    }
+}
+
+// What can be saved as a preset and is restored on launch
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DedupeSettings {
+    pub key: DedupeKey,
+    // 1-based columns holding the emails and the phone numbers; empty to look in every field
+    pub email_columns: String,
+    pub phone_columns: String,
+    // 1-based columns holding the name, which must also match; empty to ignore names
+    pub name_columns: String,
+    pub strategy: MergeStrategy,
+    pub encoding: InputEncoding,
+    pub dialect: DialectOptions,
+    // Keep going past malformed rows, listing them in `<name>_bad_records.csv` next to the output file
+    pub lenient: bool,
+    // The merged clusters are listed next to it, in `<name>_clusters.csv`
+    pub output_file: PathBuf,
+}
+
+impl Default for DedupeSettings {
+    fn default() -> Self {
+        Self {
+            key: DedupeKey::Email,
+            email_columns: String::new(),
+            phone_columns: String::new(),
+            name_columns: String::new(),
+            strategy: MergeStrategy::KeepFirst,
+            encoding: InputEncoding::Auto,
+            dialect: DialectOptions::default(),
+            lenient: false,
+            output_file: PathBuf::from("deduped.csv"),
+        }
+    }
+}
+
+impl DedupeSettings {
+    pub fn keys(&self) -> DedupeKeys {
+        DedupeKeys {
+            key: self.key,
+            email_columns: parse_columns(&self.email_columns),
+            phone_columns: parse_columns(&self.phone_columns),
+            name_columns: parse_columns(&self.name_columns),
+        }
+    }
+
+    pub fn set_keys(&mut self, keys: &DedupeKeys) {
+        self.key = keys.key;
+        self.email_columns = columns_text(&keys.email_columns);
+        self.phone_columns = columns_text(&keys.phone_columns);
+        self.name_columns = columns_text(&keys.name_columns);
+    }
+}
+
+fn parse_columns(text: &str) -> Vec<usize> {
+    text.split(',').filter_map(|column| column.trim().parse().ok()).filter(|&column| column > 0).collect()
+}
+
+fn columns_text(columns: &[usize]) -> String {
+    columns.iter().map(|column| column.to_string()).collect::<Vec<_>>().join(",")
+}
+
+// Where the clusters of `output_file` are listed
+pub fn report_path(output_file: &Path) -> PathBuf {
+    let stem = output_file.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_else(|| "deduped".to_string());
+    output_file.with_file_name(format!("{}_clusters.csv", stem))
+}
+
+pub struct DedupeTab {
+    settings: DedupeSettings,
+    presets: PresetPicker,
+    preview: PreviewPane,
+    progress: Arc<Progress>,
+    status: TabStatus,
+}
+
+impl DedupeTab {
+    pub fn new(settings: DedupeSettings) -> Self {
+        Self {
+            settings,
+            presets: PresetPicker::new("dedupe"),
+            preview: PreviewPane::new(),
+            progress: Arc::new(Progress::new()),
+            status: TabStatus::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: EventKind) {
+        self.status.apply(event);
+    }
+
+    pub fn settings(&self) -> &DedupeSettings {
+        &self.settings
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, selected_files: &mut Vec<PathBuf>, tx: &Sender<Event>) {
+        self.presets.ui(ui, &mut self.settings);
+        ui.add_space(10.0);
+
+        ui.horizontal(|ui| {
+            if ui.button(RichText::new("📁 Select CSV Files").size(18.0)).clicked() {
+                if let Some(files) = FileDialog::new()
+                    .add_filter("CSV", &["csv"])
+                    .add_filter("Archives", &archive::ARCHIVE_EXTENSIONS)
+                    .pick_files()
+                {
+                    *selected_files = files;
+                }
+            }
+            ui.label(RichText::new(format!("Selected files: {}", selected_files.len())).size(16.0));
+        });
+        self.preview.ui(ui, "dedupe_preview", selected_files, self.settings.encoding, &self.settings.dialect);
+
+        ui.add_space(10.0);
+
+        ui.horizontal(|ui| {
+            ui.label("Same contact when they share:");
+            egui::ComboBox::from_id_source("dedupe_key")
+                .selected_text(self.settings.key.label())
+                .show_ui(ui, |ui| {
+                    for key in DedupeKey::ALL {
+                        ui.selectable_value(&mut self.settings.key, key, key.label());
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Email columns:");
+            ui.add(egui::TextEdit::singleline(&mut self.settings.email_columns).hint_text("4 (empty to look in every column)"));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Phone columns:");
+            ui.add(egui::TextEdit::singleline(&mut self.settings.phone_columns).hint_text("5, 6 (empty to look in every column)"));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Name columns:");
+            ui.add(egui::TextEdit::singleline(&mut self.settings.name_columns).hint_text("2, 3 (empty to ignore names)"));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Duplicates:");
+            egui::ComboBox::from_id_source("dedupe_strategy")
+                .selected_text(self.settings.strategy.label())
+                .show_ui(ui, |ui| {
+                    for strategy in MergeStrategy::ALL {
+                        ui.selectable_value(&mut self.settings.strategy, strategy, strategy.label());
+                    }
+                });
+        });
+
+        encoding::encoding_selector(ui, "dedupe_encoding", &mut self.settings.encoding);
+        dialect::dialect_selector(ui, "dedupe_dialect", &mut self.settings.dialect, true);
+        let bad_records = records::bad_records_path(&self.settings.output_file);
+        ui.checkbox(&mut self.settings.lenient, format!("Lenient: skip or repair malformed rows and list them in {}", bad_records.display()));
+        ui.horizontal(|ui| {
+            if ui.button("Output File").clicked() {
+                if let Some(path) = FileDialog::new().add_filter("CSV", &["csv"]).set_file_name("deduped.csv").save_file() {
+                    self.settings.output_file = path;
+                }
+            }
+            ui.label(format!("Output file: {}", self.settings.output_file.display()));
+        });
+        ui.label(format!("Merged clusters: {}", report_path(&self.settings.output_file).display()));
+
+        ui.add_space(20.0);
+
+        let running = self.progress.is_running();
+        if ui.add_enabled_ui(!running, |ui| ui.add_sized([ui.available_width(), 40.0], egui::Button::new(RichText::new("🧹 Remove Duplicates").size(20.0)))).inner.clicked() {
+            if selected_files.is_empty() {
+                self.status.set("Please select the files to deduplicate.");
+            } else {
+                self.start(selected_files.clone(), tx);
+            }
+        }
+
+        ui.add_space(10.0);
+        progress::progress_panel(ui, &self.progress);
+
+        self.status.ui(ui);
+    }
+
+    pub fn pipeline_step(&self, selected_files: &[PathBuf]) -> Operation {
+        Operation::Dedupe {
+            inputs: selected_files.to_vec(),
+            encoding: self.settings.encoding,
+            dialect: self.settings.dialect,
+            lenient: self.settings.lenient,
+            keys: self.settings.keys(),
+            strategy: self.settings.strategy,
+            outputs: FileOutput { file: self.settings.output_file.clone() },
+        }
+    }
+
+    pub fn load_step(&mut self, operation: &Operation, selected_files: &mut Vec<PathBuf>) {
+        let Operation::Dedupe { inputs, encoding, dialect, lenient, keys, strategy, outputs } = operation else {
+            return;
+        };
+        self.settings.encoding = *encoding;
+        self.settings.dialect = *dialect;
+        self.settings.lenient = *lenient;
+        self.settings.set_keys(keys);
+        self.settings.strategy = *strategy;
+        self.settings.output_file = outputs.file.clone();
+        *selected_files = inputs.clone();
+    }
+
+    // Runs a job from the history again, on the same files
+    pub fn rerun(&mut self, job: &JobRecord, selected_files: &mut Vec<PathBuf>, tx: &Sender<Event>) {
+        let JobSettings::Dedupe { settings } = &job.settings else {
+            return;
+        };
+        if self.progress.is_running() {
+            self.status.set("Duplicates are still being removed");
+            return;
+        }
+        self.settings = (**settings).clone();
+        *selected_files = job.inputs.iter().map(|input| input.path.clone()).collect();
+        self.start(selected_files.clone(), tx);
+    }
+
+    fn start(&mut self, files: Vec<PathBuf>, tx: &Sender<Event>) {
+        let events = EventSender::new(TabId::Dedupe, tx);
+        let progress = self.progress.clone();
+        progress.start(&files);
+        let settings = self.settings.clone();
+        thread::spawn(move || dedupe_files(&files, &settings, &events, &progress));
+    }
+}
+
+// A record read from one of the inputs
+struct Contact {
+    file: String,
+    line: u64,
+    record: StringRecord,
+}
+
+// Finds the emails and phone numbers of records and turns them into keys
+struct KeyFinder {
+    key: DedupeKey,
+    email_columns: Vec<usize>,
+    phone_columns: Vec<usize>,
+    name_columns: Vec<usize>,
+    email_regex: Regex,
+    phone_regex: Regex,
+}
+
+impl KeyFinder {
+    fn new(settings: &DedupeSettings) -> Self {
+        Self {
+            key: settings.key,
+            email_columns: parse_columns(&settings.email_columns),
+            phone_columns: parse_columns(&settings.phone_columns),
+            name_columns: parse_columns(&settings.name_columns),
+            email_regex: formats::email_regex(),
+            phone_regex: Regex::new(r"^\+?[\d\s().\-]+$").unwrap(),
+        }
+    }
+
+    // Normalised emails found in the email columns
+    fn emails(&self, record: &StringRecord) -> Vec<String> {
+        fields(record, &self.email_columns).flat_map(|field| self.email_regex.find_iter(field)).map(|email| matching::normalize_email(email.as_str())).collect()
+    }
+
+    // The digits of phone column fields that are only a phone number, without a leading US country
+    // code. Placeholders made of one repeated digit, like 000-000-0000, are not numbers.
+    fn phones(&self, record: &StringRecord) -> Vec<String> {
+        fields(record, &self.phone_columns)
+            .filter(|field| self.phone_regex.is_match(field.trim()))
+            .filter_map(|field| {
+                let digits: String = field.chars().filter(char::is_ascii_digit).collect();
+                let digits = match digits.strip_prefix('1') {
+                    Some(national) if digits.len() == 11 => national.to_string(),
+                    _ => digits,
+                };
+                let placeholder = digits.bytes().all(|digit| digit == digits.as_bytes()[0]);
+                ((10..=15).contains(&digits.len()) && !placeholder).then_some(digits)
+            })
+            .collect()
+    }
+
+    // Lower-cased words of the name columns in sorted order, so "Smith, John" is "John Smith"
+    fn name(&self, record: &StringRecord) -> String {
+        let text = self.name_columns.iter().filter_map(|&column| record.get(column - 1)).collect::<Vec<_>>().join(" ").to_lowercase();
+        let mut words: Vec<&str> = text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).collect();
+        words.sort_unstable();
+        words.join(" ")
+    }
+
+    // Records sharing any key are the same contact. A record without one is only itself.
+    fn keys(&self, record: &StringRecord) -> Vec<String> {
+        let mut keys: Vec<String> = match self.key {
+            DedupeKey::Email => self.emails(record).into_iter().map(|email| format!("email:{}", email)).collect(),
+            DedupeKey::Phone => self.phones(record).into_iter().map(|phone| format!("phone:{}", phone)).collect(),
+            DedupeKey::EmailOrPhone => self
+                .emails(record)
+                .into_iter()
+                .map(|email| format!("email:{}", email))
+                .chain(self.phones(record).into_iter().map(|phone| format!("phone:{}", phone)))
+                .collect(),
+            // Every pairing, so a record listing two emails matches records with either of them
+            DedupeKey::EmailAndPhone => {
+                let phones = self.phones(record);
+                self.emails(record).iter().flat_map(|email| phones.iter().map(move |phone| format!("email:{} phone:{}", email, phone))).collect()
+            }
+        };
+        if !self.name_columns.is_empty() {
+            let name = self.name(record);
+            if name.is_empty() {
+                return Vec::new();
+            }
+            for key in &mut keys {
+                key.push_str(&format!(" name:{}", name));
+            }
+        }
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
+}
+
+// The fields of `columns` (1-based), or every field when there are none
+fn fields<'r>(record: &'r StringRecord, columns: &'r [usize]) -> Box<dyn Iterator<Item = &'r str> + 'r> {
+    if columns.is_empty() {
+        Box::new(record.iter())
+    } else {
+        Box::new(columns.iter().filter_map(|&column| record.get(column - 1)))
+    }
+}
+
+// Groups records sharing a key, following chains of shared keys. Returns the clusters in the order
+// of their first record, each with its records in input order and the keys they share.
+fn cluster(keys: &[Vec<String>]) -> Vec<(Vec<usize>, Vec<String>)> {
+    fn root(parents: &mut [usize], mut index: usize) -> usize {
+        while parents[index] != index {
+            parents[index] = parents[parents[index]];
+            index = parents[index];
+        }
+        index
+    }
+
+    let mut parents: Vec<usize> = (0..keys.len()).collect();
+    let mut first_with_key: HashMap<&str, usize> = HashMap::new();
+    for (index, record_keys) in keys.iter().enumerate() {
+        for key in record_keys {
+            let other = *first_with_key.entry(key.as_str()).or_insert(index);
+            let (a, b) = (root(&mut parents, index), root(&mut parents, other));
+            // The earlier record stays the root, so clusters keep the order of their first record
+            parents[a.max(b)] = a.min(b);
+        }
+    }
+
+    let mut clusters: Vec<(Vec<usize>, Vec<String>)> = Vec::new();
+    let mut cluster_of_root: HashMap<usize, usize> = HashMap::new();
+    for index in 0..keys.len() {
+        let position = *cluster_of_root.entry(root(&mut parents, index)).or_insert_with(|| {
+            clusters.push((Vec::new(), Vec::new()));
+            clusters.len() - 1
+        });
+        clusters[position].0.push(index);
+    }
+    for (members, shared) in &mut clusters {
+        if members.len() > 1 {
+            let mut counts: HashMap<&str, usize> = HashMap::new();
+            for &member in members.iter() {
+                for key in &keys[member] {
+                    *counts.entry(key).or_default() += 1;
+                }
+            }
+            *shared = counts.into_iter().filter(|(_, count)| *count > 1).map(|(key, _)| key.to_string()).collect();
+            shared.sort_unstable();
+        }
+    }
+    clusters
+}
+
+fn filled_fields(record: &StringRecord) -> usize {
+    record.iter().filter(|field| !field.trim().is_empty()).count()
+}
+
+// The record written for a cluster, from its records in input order
+fn merge(records: &[&StringRecord], strategy: MergeStrategy) -> StringRecord {
+    match strategy {
+        MergeStrategy::KeepFirst => records[0].clone(),
+        MergeStrategy::MostComplete => {
+            let mut best = records[0];
+            for &record in &records[1..] {
+                if filled_fields(record) > filled_fields(best) {
+                    best = record;
+                }
+            }
+            best.clone()
+        }
+        MergeStrategy::MergeFields => {
+            let mut fields: Vec<&str> = records[0].iter().collect();
+            for record in &records[1..] {
+                for (column, field) in record.iter().enumerate() {
+                    match fields.get_mut(column) {
+                        Some(merged) if merged.trim().is_empty() => *merged = field,
+                        Some(_) => {}
+                        None => fields.push(field),
+                    }
+                }
+            }
+            StringRecord::from(fields)
+        }
+    }
+}
+
+// Reads every record of `file`, noting the header of the first input that has one
+fn read_contacts(file: &Path, reading: &mut CsvReading, header: &mut Option<(String, StringRecord)>, events: &EventSender, progress: &Progress) -> Result<Vec<Contact>, Box<dyn std::error::Error>> {
+    let mut contacts = Vec::new();
+    archive::visit_file(file, progress, &|name| archive::has_extension(name, &["csv"]), &mut |name, reader| {
+        reading.read(name, reader, &mut |record| {
+            progress.add_rows(1);
+            let line = record.position().map_or(0, |position| position.line());
+            contacts.push(Contact { file: name.to_string(), line, record: record.clone() });
+            Ok(())
+        })?;
+        match (&*header, reading.header.take()) {
+            (None, Some(found)) => *header = Some((name.to_string(), found)),
+            (Some((first, kept)), Some(found)) if *kept != found => {
+                events.warning(format!("The header of {} differs from the one of {}, which is used for the output", name, first));
+            }
+            _ => {}
+        }
+        Ok(ControlFlow::Continue(()))
+    })?;
+    Ok(contacts)
+}
+
+// Writes one record per contact of `files` to the output file and lists the clusters of duplicates
+// that were merged next to it, for the tab or a pipeline step. `progress` has already been started
+// by the caller.
+pub fn dedupe_files(files: &[PathBuf], settings: &DedupeSettings, events: &EventSender, progress: &Progress) {
+    let started = Instant::now();
+    let mut errors = 0;
+    events.started(format!("Removing duplicates from {} files...", files.len()));
+    let inputs = files.iter().map(|file| InputFile::hash(file)).collect();
+    let output_file = settings.output_file.clone();
+    let mut reading = CsvReading::new(settings.encoding, settings.dialect, settings.lenient, Some(&records::bad_records_path(&output_file)), events);
+    let mut header = None;
+    let mut contacts = Vec::new();
+    for file in files {
+        progress.set_current_file(&file.display().to_string());
+        match read_contacts(file, &mut reading, &mut header, events, progress) {
+            Ok(read) => {
+                events.file_done(file.display().to_string(), format!("{} records", read.len()));
+                contacts.extend(read);
+            }
+            Err(e) => {
+                errors += 1;
+                events.file_error(&file.display().to_string(), format!("Error processing {}: {}", file.display(), e));
+            }
+        }
+        progress.file_done();
+    }
+    let mut outputs: Vec<OutputFile> = reading.finish(events).into_iter().collect();
+
+    let finder = KeyFinder::new(settings);
+    let keys: Vec<Vec<String>> = contacts.iter().map(|contact| finder.keys(&contact.record)).collect();
+    let clusters = cluster(&keys);
+    let header = header.map(|(_, header)| header);
+    let message = match write_outputs(&contacts, &clusters, header.as_ref(), settings.strategy, &output_file) {
+        Ok((written, merged)) => {
+            outputs.push(OutputFile { path: output_file.clone(), rows: written });
+            outputs.push(OutputFile { path: report_path(&output_file), rows: merged });
+            format!(
+                "{} records read, {} duplicates removed in {} clusters, saved to '{}'",
+                contacts.len(),
+                contacts.len() - clusters.len(),
+                clusters.iter().filter(|(members, _)| members.len() > 1).count(),
+                output_file.display()
+            )
+        }
+        Err(e) => {
+            errors += 1;
+            events.error(format!("Error saving the deduplicated records: {}", e));
+            "Deduplicated records could not be saved".to_string()
+        }
+    };
+    // Clustering and writing can take a while, so the job only stops running once they are done
+    progress.finish();
+    let details = JobDetails { settings: JobSettings::Dedupe { settings: Box::new(settings.clone()) }, inputs, outputs, rows: progress.snapshot().rows };
+    events.finished(Summary { message, files: files.len(), errors, elapsed: started.elapsed(), details });
+}
+
+// Returns the rows written to the output and to the report, headers included
+fn write_outputs(
+    contacts: &[Contact],
+    clusters: &[(Vec<usize>, Vec<String>)],
+    header: Option<&StringRecord>,
+    strategy: MergeStrategy,
+    output_file: &Path,
+) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+    // Merged records can be longer than the header
+    let mut output = WriterBuilder::new().flexible(true).from_path(output_file)?;
+    let mut report = WriterBuilder::new().from_path(report_path(output_file))?;
+    let mut written = 0;
+    if let Some(header) = header {
+        output.write_record(header)?;
+        written += 1;
+    }
+    report.write_record(["cluster", "records", "shared keys", "source", "line", "record"])?;
+    let mut reported = 1;
+    let mut merged_clusters = 0;
+    for (members, shared) in clusters {
+        let records: Vec<&StringRecord> = members.iter().map(|&member| &contacts[member].record).collect();
+        let merged = merge(&records, strategy);
+        output.write_record(&merged)?;
+        written += 1;
+        if members.len() == 1 {
+            continue;
+        }
+        merged_clusters += 1;
+        let cluster = merged_clusters.to_string();
+        let count = members.len().to_string();
+        let shared = shared.join("; ");
+        report.write_record([cluster.as_str(), &count, &shared, "(written)", "", &formats::record_text(&merged, b',')])?;
+        for &member in members {
+            let contact = &contacts[member];
+            let line = contact.line.to_string();
+            report.write_record([cluster.as_str(), &count, &shared, &contact.file, &line, &formats::record_text(&contact.record, b',')])?;
+        }
+        reported += members.len() as u64 + 1;
+    }
+    output.flush()?;
+    report.flush()?;
+    Ok((written, reported))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finder(key: DedupeKey, name_columns: &str) -> KeyFinder {
+        KeyFinder::new(&DedupeSettings { key, name_columns: name_columns.to_string(), ..Default::default() })
+    }
+
+    fn record(fields: &[&str]) -> StringRecord {
+        StringRecord::from(fields.to_vec())
+    }
+
+    fn keys(lists: &[&[&str]]) -> Vec<Vec<String>> {
+        lists.iter().map(|list| list.iter().map(|key| key.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn clusters_follow_chains_of_shared_keys() {
+        // 0 and 2 share nothing, but both share a key with 1
+        let clusters = cluster(&keys(&[&["a"], &["a", "b"], &["b"], &["c"]]));
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].0, vec![0, 1, 2]);
+        assert_eq!(clusters[0].1, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(clusters[1].0, vec![3]);
+        assert!(clusters[1].1.is_empty());
+    }
+
+    #[test]
+    fn clusters_keep_the_order_of_their_first_record() {
+        // The later cluster is joined to the earlier one through record 3
+        let clusters = cluster(&keys(&[&["x"], &["y"], &["z"], &["z", "x"], &[]]));
+        let members: Vec<Vec<usize>> = clusters.into_iter().map(|(members, _)| members).collect();
+        assert_eq!(members, vec![vec![0, 2, 3], vec![1], vec![4]]);
+    }
+
+    #[test]
+    fn most_complete_keeps_the_first_on_a_tie() {
+        let first = record(&["Ann", "", "ann@example.com"]);
+        let second = record(&["", "555", "ann@example.com"]);
+        let third = record(&["Ann", "555", "ann@example.com"]);
+        assert_eq!(merge(&[&first, &second], MergeStrategy::MostComplete), first);
+        assert_eq!(merge(&[&first, &second, &third], MergeStrategy::MostComplete), third);
+    }
+
+    #[test]
+    fn merge_fields_fills_the_empty_fields_of_the_first() {
+        let first = record(&["Ann", "", "ann@example.com"]);
+        let second = record(&["Anne", "555", "", "NY"]);
+        assert_eq!(merge(&[&first, &second], MergeStrategy::MergeFields), record(&["Ann", "555", "ann@example.com", "NY"]));
+        assert_eq!(merge(&[&first, &second], MergeStrategy::KeepFirst), first);
+    }
+
+    #[test]
+    fn phones_drop_the_us_country_code() {
+        let finder = finder(DedupeKey::Phone, "");
+        assert_eq!(finder.phones(&record(&["+1 (555) 123-4567"])), vec!["5551234567"]);
+        assert_eq!(finder.phones(&record(&["555.123.4567"])), vec!["5551234567"]);
+        assert_eq!(finder.phones(&record(&["+44 20 7946 0958"])), vec!["442079460958"]);
+    }
+
+    #[test]
+    fn phones_skip_placeholders_and_other_text() {
+        let finder = finder(DedupeKey::Phone, "");
+        assert!(finder.phones(&record(&["000-000-0000", "(999) 999-9999", "call 555 123 4567", "12345"])).is_empty());
+    }
+
+    #[test]
+    fn email_and_phone_pairs_every_email_with_every_phone() {
+        let finder = finder(DedupeKey::EmailAndPhone, "");
+        let found = finder.keys(&record(&["a@example.com", "b@example.com", "555-123-4567"]));
+        assert_eq!(found, vec!["email:a@example.com phone:5551234567", "email:b@example.com phone:5551234567"]);
+        assert!(finder.keys(&record(&["a@example.com"])).is_empty());
+    }
+
+    #[test]
+    fn names_must_match_when_name_columns_are_set() {
+        let finder = finder(DedupeKey::Email, "1");
+        let smith = finder.keys(&record(&["Smith, John", "john@example.com"]));
+        assert_eq!(smith, finder.keys(&record(&["john smith", "JOHN@example.com"])));
+        assert_ne!(smith, finder.keys(&record(&["Jane Smith", "john@example.com"])));
+        // A record without a name can't be matched on it
+        assert!(finder.keys(&record(&["", "john@example.com"])).is_empty());
+    }
+}
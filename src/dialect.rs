@@ -0,0 +1,471 @@
+use chrono::NaiveDate;
+use csv::{ReaderBuilder, StringRecord};
+use eframe::egui;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Cursor, Read};
+use crate::formats;
+
+// Bytes read from the start of an input to detect its dialect
+const SAMPLE_BYTES: u64 = 64 * 1024;
+// Records parsed from the sample to decide whether the first one is a header
+const HEADER_SAMPLE_RECORDS: usize = 20;
+const DELIMITERS: [(char, &str); 4] = [(',', "Comma"), (';', "Semicolon"), ('\t', "Tab"), ('|', "Pipe")];
+const QUOTES: [(char, &str); 2] = [('"', "Double quote"), ('\'', "Single quote")];
+const DATE_FORMATS: [&str; 4] = ["%Y-%m-%d", "%m/%d/%Y", "%d.%m.%Y", "%Y/%m/%d"];
+
+// How a quote is written inside a quoted field
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Escape {
+    // `"say ""hi"""`
+    Doubled,
+    // `"say \"hi\""`
+    Backslash,
+}
+
+impl Escape {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Escape::Doubled => "Doubled quotes",
+            Escape::Backslash => "Backslash",
+        }
+    }
+}
+
+// The dialect of one input, detected or chosen
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Dialect {
+    pub delimiter: u8,
+    pub quote: u8,
+    pub escape: Escape,
+    pub has_header: bool,
+}
+
+impl Dialect {
+    // A reader for this dialect. Records must have the same number of fields.
+    pub fn reader_builder(&self) -> ReaderBuilder {
+        let mut builder = ReaderBuilder::new();
+        builder.has_headers(self.has_header).delimiter(self.delimiter).quote(self.quote);
+        if self.escape == Escape::Backslash {
+            builder.escape(Some(b'\\')).double_quote(false);
+        }
+        builder
+    }
+
+    // Every part fixed, so a later part of the same file is read the same way
+    pub fn options(&self) -> DialectOptions {
+        DialectOptions {
+            delimiter: Some(self.delimiter as char),
+            quote: Some(self.quote as char),
+            escape: Some(self.escape),
+            has_header: Some(self.has_header),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{}, {} quotes, {}",
+            delimiter_label(self.delimiter as char),
+            self.quote as char,
+            if self.has_header { "header" } else { "no header" }
+        )
+    }
+}
+
+fn delimiter_label(delimiter: char) -> String {
+    match DELIMITERS.iter().find(|(c, _)| *c == delimiter) {
+        Some((_, label)) => label.to_string(),
+        None => format!("'{}'", delimiter),
+    }
+}
+
+// What to use instead of detecting each part of the dialect; `None` detects it
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DialectOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<char>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quote: Option<char>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub escape: Option<Escape>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_header: Option<bool>,
+}
+
+impl DialectOptions {
+    pub fn is_auto(&self) -> bool {
+        *self == DialectOptions::default()
+    }
+
+    // For jobs where a wrong guess would lose a data row, e.g. splitting: the first row is only
+    // taken as a header when that was chosen
+    pub fn header_if_chosen(&self) -> DialectOptions {
+        DialectOptions { has_header: Some(self.has_header == Some(true)), ..*self }
+    }
+}
+
+// The csv reader only takes single-byte delimiters and quotes
+fn ascii(choice: Option<char>) -> Option<u8> {
+    choice.filter(char::is_ascii).map(|c| c as u8)
+}
+
+// Detects the parts of the dialect of `sample`, the start of a UTF-8 input, that `options` leaves open
+pub fn sniff(sample: &[u8], options: &DialectOptions) -> Dialect {
+    let quote = ascii(options.quote).unwrap_or_else(|| guess_quote(sample));
+    let escape = options.escape.unwrap_or_else(|| guess_escape(sample, quote));
+    let delimiter = ascii(options.delimiter).unwrap_or_else(|| guess_delimiter(sample, quote, escape));
+    let mut dialect = Dialect { delimiter, quote, escape, has_header: false };
+    dialect.has_header = options.has_header.unwrap_or_else(|| guess_header(sample, &dialect));
+    dialect
+}
+
+// Detects the dialect of a decoded input from its first bytes. The returned reader still
+// yields the whole input.
+pub fn detect<'a>(mut input: Box<dyn Read + 'a>, options: &DialectOptions) -> io::Result<(Box<dyn Read + 'a>, Dialect)> {
+    let mut sample = Vec::new();
+    (&mut input).take(SAMPLE_BYTES).read_to_end(&mut sample)?;
+    // A line cut off by the sample limit would look inconsistent
+    let complete = match sample.iter().rposition(|&b| b == b'\n') {
+        Some(end) if sample.len() as u64 == SAMPLE_BYTES => &sample[..=end],
+        _ => &sample[..],
+    };
+    let dialect = sniff(complete, options);
+    Ok((Box::new(Cursor::new(sample).chain(input)), dialect))
+}
+
+fn is_delimiter_candidate(byte: u8) -> bool {
+    DELIMITERS.iter().any(|(c, _)| *c as u8 == byte)
+}
+
+fn is_field_edge(byte: u8) -> bool {
+    matches!(byte, b'\n' | b'\r') || is_delimiter_candidate(byte)
+}
+
+// How many whole fields `quote` encloses on one line: opened right after a delimiter or a line
+// break, and closed right before one. An apostrophe at the start or end of a word does not count
+// unless another one closes the field.
+fn quoted_fields(sample: &[u8], quote: u8) -> usize {
+    let mut count = 0;
+    let mut start = 0;
+    while start < sample.len() {
+        let opens = sample[start] == quote && (start == 0 || is_field_edge(sample[start - 1]));
+        if !opens {
+            start += 1;
+            continue;
+        }
+        // The closing quote, skipping doubled and backslash-escaped ones
+        let mut end = start + 1;
+        let closed = loop {
+            match sample.get(end) {
+                None | Some(b'\n') => break false,
+                Some(&b) if b == quote && sample.get(end + 1) == Some(&quote) => end += 2,
+                Some(&b) if b == quote && sample[end - 1] != b'\\' => break true,
+                Some(_) => end += 1,
+            }
+        };
+        if closed && !matches!(sample.get(end + 1), Some(&after) if !is_field_edge(after)) {
+            count += 1;
+            start = end + 1;
+        } else {
+            start += 1;
+        }
+    }
+    count
+}
+
+// The quote that encloses the most fields; double quotes unless single ones enclose more
+fn guess_quote(sample: &[u8]) -> u8 {
+    if quoted_fields(sample, b'\'') > quoted_fields(sample, b'"') {
+        b'\''
+    } else {
+        b'"'
+    }
+}
+
+fn guess_escape(sample: &[u8], quote: u8) -> Escape {
+    let escaped = sample.windows(2).filter(|pair| pair[0] == b'\\' && pair[1] == quote).count();
+    let doubled = sample.windows(2).filter(|pair| pair[0] == quote && pair[1] == quote).count();
+    if escaped > 0 && escaped >= doubled {
+        Escape::Backslash
+    } else {
+        Escape::Doubled
+    }
+}
+
+// The candidate that splits the most records into the same number of fields, counting only
+// delimiters outside quotes. Comma wins ties.
+fn guess_delimiter(sample: &[u8], quote: u8, escape: Escape) -> u8 {
+    // Per record, how often each candidate appears
+    let mut records: Vec<[usize; DELIMITERS.len()]> = Vec::new();
+    let mut counts = [0; DELIMITERS.len()];
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for &byte in sample {
+        if escaped {
+            escaped = false;
+        } else if in_quotes && escape == Escape::Backslash && byte == b'\\' {
+            escaped = true;
+        } else if byte == quote {
+            in_quotes = !in_quotes;
+        } else if !in_quotes && byte == b'\n' {
+            if counts.iter().any(|&count| count > 0) {
+                records.push(counts);
+            }
+            counts = [0; DELIMITERS.len()];
+        } else if !in_quotes {
+            if let Some(index) = DELIMITERS.iter().position(|(c, _)| *c as u8 == byte) {
+                counts[index] += 1;
+            }
+        }
+    }
+    if counts.iter().any(|&count| count > 0) {
+        records.push(counts);
+    }
+
+    let mut best = (b',', (0, 0));
+    for (index, (delimiter, _)) in DELIMITERS.iter().enumerate() {
+        let mut modes: Vec<(usize, usize)> = Vec::new();
+        for count in records.iter().map(|counts| counts[index]).filter(|&count| count > 0) {
+            match modes.iter_mut().find(|(value, _)| *value == count) {
+                Some((_, seen)) => *seen += 1,
+                None => modes.push((count, 1)),
+            }
+        }
+        // Records agreeing on the most common count, then the count itself
+        let score = modes.iter().map(|&(value, seen)| (seen, value)).max().unwrap_or((0, 0));
+        if score > best.1 {
+            best = (*delimiter as u8, score);
+        }
+    }
+    best.0
+}
+
+fn guess_header(sample: &[u8], dialect: &Dialect) -> bool {
+    let mut builder = dialect.reader_builder();
+    builder.has_headers(false).flexible(true);
+    let records: Vec<StringRecord> = builder.from_reader(sample).records().take(HEADER_SAMPLE_RECORDS + 1).filter_map(Result::ok).collect();
+    match records.split_first() {
+        Some((first, rest)) => looks_like_header(first, rest, &formats::email_regex()),
+        None => false,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColumnType {
+    Empty,
+    Integer,
+    Decimal,
+    Date,
+    Email,
+    Phone,
+    Text,
+}
+
+impl ColumnType {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ColumnType::Empty => "empty",
+            ColumnType::Integer => "integer",
+            ColumnType::Decimal => "decimal",
+            ColumnType::Date => "date",
+            ColumnType::Email => "email",
+            ColumnType::Phone => "phone",
+            ColumnType::Text => "text",
+        }
+    }
+
+    fn of(field: &str, email_regex: &Regex) -> ColumnType {
+        let field = field.trim();
+        let digits = field.chars().filter(|c| c.is_ascii_digit()).count();
+        if field.is_empty() {
+            ColumnType::Empty
+        } else if field.parse::<i64>().is_ok() {
+            ColumnType::Integer
+        // European exports write decimals with a comma
+        } else if digits > 0 && field.replacen(',', ".", 1).parse::<f64>().is_ok() {
+            ColumnType::Decimal
+        } else if DATE_FORMATS.iter().any(|format| NaiveDate::parse_from_str(field, format).is_ok()) {
+            ColumnType::Date
+        } else if email_regex.find(field).is_some_and(|found| found.len() == field.len()) {
+            ColumnType::Email
+        } else if (7..=15).contains(&digits) && field.chars().all(|c| c.is_ascii_digit() || " ()+-.".contains(c)) {
+            ColumnType::Phone
+        } else {
+            ColumnType::Text
+        }
+    }
+
+    // The type of a column holding values of both types
+    fn merge(self, other: ColumnType) -> ColumnType {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ColumnType::Empty, t) | (t, ColumnType::Empty) => t,
+            (ColumnType::Integer, ColumnType::Decimal) | (ColumnType::Decimal, ColumnType::Integer) => ColumnType::Decimal,
+            _ => ColumnType::Text,
+        }
+    }
+}
+
+pub fn column_types(records: &[StringRecord], email_regex: &Regex) -> Vec<ColumnType> {
+    let mut types: Vec<ColumnType> = Vec::new();
+    for record in records {
+        for (index, field) in record.iter().enumerate() {
+            let field_type = ColumnType::of(field, email_regex);
+            match types.get_mut(index) {
+                Some(column) => *column = column.merge(field_type),
+                None => types.push(field_type),
+            }
+        }
+    }
+    types
+}
+
+// Each column votes, as Python's csv.Sniffer does: when its other values share a type, for a
+// header if the first field has another type; when they are text of one length, for a header if
+// the first field has another length
+fn looks_like_header(first: &StringRecord, rest: &[StringRecord], email_regex: &Regex) -> bool {
+    let types = column_types(rest, email_regex);
+    let mut votes = 0;
+    for (index, (field, column)) in first.iter().zip(types).enumerate() {
+        match column {
+            ColumnType::Empty => {}
+            ColumnType::Text => {
+                let mut lengths = rest.iter().filter_map(|record| record.get(index)).map(|value| value.chars().count());
+                let Some(length) = lengths.next() else {
+                    continue;
+                };
+                if lengths.all(|other| other == length) {
+                    votes += if field.chars().count() != length { 1 } else { -1 };
+                }
+            }
+            column => votes += if ColumnType::of(field, email_regex) != column { 1 } else { -1 },
+        }
+    }
+    votes > 0
+}
+
+// `detect_header` is false for jobs that only skip a header when it is chosen, see `header_if_chosen`
+pub fn dialect_selector(ui: &mut egui::Ui, id_source: &str, options: &mut DialectOptions, detect_header: bool) {
+    ui.horizontal(|ui| {
+        ui.label("Delimiter:");
+        egui::ComboBox::from_id_source((id_source, "delimiter"))
+            .selected_text(options.delimiter.map_or("Auto".to_string(), delimiter_label))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut options.delimiter, None, "Auto");
+                for (delimiter, label) in DELIMITERS {
+                    ui.selectable_value(&mut options.delimiter, Some(delimiter), label);
+                }
+            });
+        ui.label("Quote:");
+        egui::ComboBox::from_id_source((id_source, "quote"))
+            .selected_text(options.quote.map_or("Auto".to_string(), |quote| quote.to_string()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut options.quote, None, "Auto");
+                for (quote, label) in QUOTES {
+                    ui.selectable_value(&mut options.quote, Some(quote), label);
+                }
+            });
+        ui.label("Escape:");
+        egui::ComboBox::from_id_source((id_source, "escape"))
+            .selected_text(options.escape.map_or("Auto", |escape| escape.label()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut options.escape, None, "Auto");
+                for escape in [Escape::Doubled, Escape::Backslash] {
+                    ui.selectable_value(&mut options.escape, Some(escape), escape.label());
+                }
+            });
+        ui.label("Header:");
+        egui::ComboBox::from_id_source((id_source, "header"))
+            .selected_text(match options.has_header {
+                None if detect_header => "Auto",
+                Some(true) => "Yes",
+                _ => "No",
+            })
+            .show_ui(ui, |ui| {
+                if detect_header {
+                    ui.selectable_value(&mut options.has_header, None, "Auto");
+                }
+                ui.selectable_value(&mut options.has_header, Some(true), "Yes");
+                ui.selectable_value(&mut options.has_header, Some(false), "No");
+            });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dialect(delimiter: u8) -> Dialect {
+        Dialect { delimiter, quote: b'"', escape: Escape::Doubled, has_header: false }
+    }
+
+    #[test]
+    fn quote_is_double_by_default() {
+        assert_eq!(guess_quote(b"name,email\nBob,bob@example.com\n"), b'"');
+        assert_eq!(guess_quote(b"\"Smith, John\",NY\n\"Lee, Ann\",OH\n"), b'"');
+    }
+
+    #[test]
+    fn quote_is_single_when_it_encloses_more_fields() {
+        assert_eq!(guess_quote(b"'Smith, John','NY'\n'Lee, Ann','OH'\n"), b'\'');
+    }
+
+    #[test]
+    fn apostrophes_at_field_edges_are_not_quotes() {
+        let sample = b"name,note\nJones',students' work\n'90s,rock 'n' roll\nO'Neil,'tis\n";
+        assert_eq!(guess_quote(sample), b'"');
+    }
+
+    #[test]
+    fn delimiter_is_the_most_consistent_candidate() {
+        assert_eq!(guess_delimiter(b"a;b;c\n1;2;3\n4;5;6\n", b'"', Escape::Doubled), b';');
+        assert_eq!(guess_delimiter(b"a\tb\n1\t2\n", b'"', Escape::Doubled), b'\t');
+        assert_eq!(guess_delimiter(b"a|b|c\n1|2|3\n", b'"', Escape::Doubled), b'|');
+    }
+
+    #[test]
+    fn delimiters_inside_quotes_do_not_count() {
+        let sample = b"name;city\n\"Smith, John\";\"Albany, NY\"\n\"Lee, Ann\";\"Dayton, OH\"\n";
+        assert_eq!(guess_delimiter(sample, b'"', Escape::Doubled), b';');
+        let escaped = b"a;b\n\"say \\\"hi, there\\\"\";x\n";
+        assert_eq!(guess_delimiter(escaped, b'"', Escape::Backslash), b';');
+    }
+
+    #[test]
+    fn comma_wins_ties() {
+        assert_eq!(guess_delimiter(b"a,b;c\n1,2;3\n", b'"', Escape::Doubled), b',');
+        assert_eq!(guess_delimiter(b"single column\n", b'"', Escape::Doubled), b',');
+    }
+
+    #[test]
+    fn header_is_detected_from_column_types() {
+        let sample = b"name,age,email\nBob,34,bob@example.com\nAnn,29,ann@example.com\n";
+        assert!(guess_header(sample, &dialect(b',')));
+        let decimals = b"product;price\nTea;3,50\nCoffee;4,20\n";
+        assert!(guess_header(decimals, &dialect(b';')));
+    }
+
+    #[test]
+    fn data_rows_are_not_taken_for_a_header() {
+        let sample = b"Bob,34,bob@example.com\nAnn,29,ann@example.com\nEve,41,eve@example.com\n";
+        assert!(!guess_header(sample, &dialect(b',')));
+        let states = b"John Smith,NY,john@gmail.com\nAnn Lee,OH,ann@gmail.com\n";
+        assert!(!guess_header(states, &dialect(b',')));
+    }
+
+    #[test]
+    fn chosen_options_override_detection() {
+        let options = DialectOptions { delimiter: Some(';'), quote: Some('\''), escape: None, has_header: Some(false) };
+        let sniffed = sniff(b"name,age\nBob,34\n", &options);
+        assert_eq!((sniffed.delimiter, sniffed.quote, sniffed.has_header), (b';', b'\'', false));
+    }
+
+    #[test]
+    fn header_is_only_skipped_when_chosen() {
+        assert_eq!(DialectOptions::default().header_if_chosen().has_header, Some(false));
+        let chosen = DialectOptions { has_header: Some(true), ..Default::default() };
+        assert_eq!(chosen.header_if_chosen().has_header, Some(true));
+    }
+}
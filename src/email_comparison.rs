@@ -1,36 +1,63 @@
 use eframe::egui;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Instant;
 use std::collections::HashSet;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Write};
 use rfd::FileDialog;
+use serde::{Deserialize, Serialize};
+use crate::encoding::{self, InputEncoding};
+use crate::events::{Event, EventKind, EventSender, Summary, TabId, TabStatus};
+use crate::history::{InputFile, JobDetails, JobRecord, JobSettings, OutputFile};
+use crate::pipeline::{FileOutput, Operation};
+use crate::presets::PresetPicker;
+
+// What can be saved as a preset and is restored on launch
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailComparisonSettings {
+    pub file1_path: Option<PathBuf>,
+    pub file2_path: Option<PathBuf>,
+    pub output_path: Option<PathBuf>,
+    pub encoding: InputEncoding,
+}
 
 pub struct EmailComparisonTab {
-    file1_path: Option<PathBuf>,
-    file2_path: Option<PathBuf>,
-    output_path: Option<PathBuf>,
+    settings: EmailComparisonSettings,
+    presets: PresetPicker,
+    status: TabStatus,
 }
 
 impl EmailComparisonTab {
-    pub fn new() -> Self {
+    pub fn new(settings: EmailComparisonSettings) -> Self {
         Self {
-            file1_path: None,
-            file2_path: None,
-            output_path: None,
+            settings,
+            presets: PresetPicker::new("email_comparison"),
+            status: TabStatus::new(),
         }
     }
 
-    pub fn ui(&mut self, ui: &mut egui::Ui, processing_status: &mut String, tx: &Sender<String>) {
+    pub fn handle_event(&mut self, event: EventKind) {
+        self.status.apply(event);
+    }
+
+    pub fn settings(&self) -> &EmailComparisonSettings {
+        &self.settings
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, tx: &Sender<Event>) {
         ui.heading("Email Comparison");
+        self.presets.ui(ui, &mut self.settings);
 
         ui.horizontal(|ui| {
             if ui.button("Select First Email List").clicked() {
                 if let Some(path) = FileDialog::new().add_filter("Text file", &["txt"]).pick_file() {
-                    self.file1_path = Some(path);
+                    self.settings.file1_path = Some(path);
                 }
             }
-            if let Some(path) = &self.file1_path {
+            if let Some(path) = &self.settings.file1_path {
                 ui.label(format!("First file: {}", path.display()));
             }
         });
@@ -38,10 +65,10 @@ impl EmailComparisonTab {
         ui.horizontal(|ui| {
             if ui.button("Select Second Email List").clicked() {
                 if let Some(path) = FileDialog::new().add_filter("Text file", &["txt"]).pick_file() {
-                    self.file2_path = Some(path);
+                    self.settings.file2_path = Some(path);
                 }
             }
-            if let Some(path) = &self.file2_path {
+            if let Some(path) = &self.settings.file2_path {
                 ui.label(format!("Second file: {}", path.display()));
             }
         });
@@ -49,51 +76,118 @@ impl EmailComparisonTab {
         ui.horizontal(|ui| {
             if ui.button("Select Output File").clicked() {
                 if let Some(path) = FileDialog::new().add_filter("Text file", &["txt"]).save_file() {
-                    self.output_path = Some(path);
+                    self.settings.output_path = Some(path);
                 }
             }
-            if let Some(path) = &self.output_path {
+            if let Some(path) = &self.settings.output_path {
                 ui.label(format!("Output file: {}", path.display()));
             }
         });
 
+        encoding::encoding_selector(ui, "email_comparison_encoding", &mut self.settings.encoding);
+
         if ui.button("Compare and Output Unique Emails").clicked() {
-            if let (Some(file1), Some(file2), Some(output)) = (&self.file1_path, &self.file2_path, &self.output_path) {
-                match self.compare_email_lists(file1, file2, output) {
-                    Ok(unique_count) => {
-                        *processing_status = format!("Comparison complete. {} unique emails found.", unique_count);
-                    }
-                    Err(e) => {
-                        *processing_status = format!("Error during comparison: {}", e);
-                    }
-                }
+            if let (Some(file1), Some(file2), Some(output)) = (self.settings.file1_path.clone(), self.settings.file2_path.clone(), self.settings.output_path.clone()) {
+                self.start(file1, file2, output, tx);
             } else {
-                *processing_status = "Please select both input files and an output file.".to_string();
+                self.status.set("Please select both input files and an output file.");
             }
         }
+
+        self.status.ui(ui);
     }
 
-    fn compare_email_lists(&self, file1: &PathBuf, file2: &PathBuf, output: &PathBuf) -> Result<usize, Box<dyn std::error::Error>> {
-        let emails1 = self.read_emails(file1)?;
-        let emails2 = self.read_emails(file2)?;
+    pub fn pipeline_step(&self) -> Operation {
+        Operation::EmailComparison {
+            inputs: self.settings.file1_path.iter().chain(&self.settings.file2_path).cloned().collect(),
+            encoding: self.settings.encoding,
+            outputs: FileOutput { file: self.settings.output_path.clone().unwrap_or_default() },
+        }
+    }
 
-        let unique_emails: HashSet<_> = emails1.symmetric_difference(&emails2).collect();
+    pub fn load_step(&mut self, operation: &Operation) {
+        let Operation::EmailComparison { inputs, encoding, outputs } = operation else {
+            return;
+        };
+        self.settings.file1_path = inputs.first().cloned();
+        self.settings.file2_path = inputs.get(1).cloned();
+        self.settings.output_path = Some(outputs.file.clone()).filter(|path| !path.as_os_str().is_empty());
+        self.settings.encoding = *encoding;
+    }
 
-        let mut output_file = File::create(output)?;
-        for email in &unique_emails {
-            writeln!(output_file, "{}", email)?;
-        }
-        Ok(unique_emails.len())
+    // Runs a comparison from the history again, on the same lists
+    pub fn rerun(&mut self, job: &JobRecord, tx: &Sender<Event>) {
+        let JobSettings::EmailComparison { first, second, output, encoding } = &job.settings else {
+            return;
+        };
+        self.settings.file1_path = Some(first.clone());
+        self.settings.file2_path = Some(second.clone());
+        self.settings.output_path = Some(output.clone());
+        self.settings.encoding = *encoding;
+        self.start(first.clone(), second.clone(), output.clone(), tx);
     }
 
-    fn read_emails(&self, file_path: &PathBuf) -> Result<HashSet<String>, Box<dyn std::error::Error>> {
-        let file = File::open(file_path)?;
-        let reader = BufReader::new(file);
-        let emails: HashSet<String> = reader.lines()
-            .filter_map(Result::ok)
-            .map(|line| line.trim().to_lowercase())
-            .filter(|line| !line.is_empty())
-            .collect();
-        Ok(emails)
+    fn start(&mut self, file1: PathBuf, file2: PathBuf, output: PathBuf, tx: &Sender<Event>) {
+        let events = EventSender::new(TabId::EmailComparison, tx);
+        let encoding = self.settings.encoding;
+        thread::spawn(move || compare_files(file1, file2, output, encoding, &events));
     }
-}
\ No newline at end of file
+}
+
+// Writes the emails found in only one of the two lists to `output`, for the tab or a pipeline step
+pub fn compare_files(file1: PathBuf, file2: PathBuf, output: PathBuf, encoding: InputEncoding, events: &EventSender) {
+    let started = Instant::now();
+    events.started("Comparing email lists...");
+    let inputs = vec![InputFile::hash(&file1), InputFile::hash(&file2)];
+    let mut outputs = Vec::new();
+    let mut rows = 0;
+    let (message, errors) = match compare_email_lists(&file1, &file2, &output, encoding) {
+        Ok((read, unique_count)) => {
+            rows = read;
+            outputs.push(OutputFile { path: output.clone(), rows: unique_count as u64 });
+            (format!("Comparison complete. {} unique emails found.", unique_count), 0)
+        }
+        Err(e) => {
+            events.error(format!("Error during comparison: {}", e));
+            ("Comparison failed".to_string(), 1)
+        }
+    };
+    let settings = JobSettings::EmailComparison { first: file1, second: file2, output, encoding };
+    let details = JobDetails { settings, inputs, outputs, rows };
+    events.finished(Summary { message, files: 2, errors, elapsed: started.elapsed(), details });
+}
+
+// Returns how many distinct emails were read from both lists and how many were written
+fn compare_email_lists(file1: &Path, file2: &Path, output: &Path, encoding: InputEncoding) -> Result<(u64, usize), Box<dyn std::error::Error>> {
+    let emails1 = read_emails(file1, encoding)?;
+    let emails2 = read_emails(file2, encoding)?;
+
+    let (only_first, only_second) = compare_emails(&emails1, &emails2);
+
+    let mut output_file = File::create(output)?;
+    for email in only_first.iter().chain(&only_second) {
+        writeln!(output_file, "{}", email)?;
+    }
+    Ok(((emails1.len() + emails2.len()) as u64, only_first.len() + only_second.len()))
+}
+
+// Splits the emails found in only one of two lists into those of the first and those of the
+// second, each sorted
+pub fn compare_emails(emails1: &HashSet<String>, emails2: &HashSet<String>) -> (Vec<String>, Vec<String>) {
+    let mut only_first: Vec<String> = emails1.difference(emails2).cloned().collect();
+    let mut only_second: Vec<String> = emails2.difference(emails1).cloned().collect();
+    only_first.sort_unstable();
+    only_second.sort_unstable();
+    (only_first, only_second)
+}
+
+pub fn read_emails(file_path: &Path, encoding: InputEncoding) -> Result<HashSet<String>, Box<dyn std::error::Error>> {
+    let (input, _) = encoding::open_file(file_path, encoding)?;
+    let reader = BufReader::new(input);
+    let emails: HashSet<String> = reader.lines()
+        .map_while(Result::ok)
+        .map(|line| line.trim().to_lowercase())
+        .filter(|line| !line.is_empty())
+        .collect();
+    Ok(emails)
+}
@@ -1,61 +1,293 @@
 use eframe::egui;
 use std::path::{PathBuf, Path};
-use std::sync::{Arc, Mutex, mpsc::{Sender, Receiver, channel}};
+use std::sync::{Arc, Mutex, mpsc::Sender};
 use std::thread;
 use egui::RichText;
 use rfd::FileDialog;
 use std::fs::{self, File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
-use encoding_rs_io::DecodeReaderBytesBuilder;
-use encoding_rs::WINDOWS_1252;
-use std::sync::mpsc;
+use std::io::{BufRead, BufReader, Read};
+use std::ops::ControlFlow;
+use std::panic::{self, AssertUnwindSafe};
+use crate::archive;
+use crate::chunks;
+use crate::dialect::{self, DialectOptions};
+use crate::encoding::{self, InputEncoding};
+use crate::events::{Event, EventKind, EventSender, Summary, TabId, TabStatus};
+use crate::formats::{self, FileFormat};
+use crate::history::{InputFile, JobDetails, JobRecord, JobSettings, OutputFile};
+use crate::job::{JobControl, MemoryBudget};
+use crate::pipeline::{Operation, SearchOutputs};
+use crate::presets::PresetPicker;
+use crate::progress::{self, Progress};
+use crate::matching::{ListMatcher, MatchMode, QueryMode, SearchQuery};
+use crate::results_table::{self, ResultsTable, SearchMatch, RESULTS_HEADER};
+use crate::search_state::{SearchState, STATE_FILE};
+use crate::walk::{self, SymlinkPolicy, WalkOptions};
+use chrono::Local;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
 use rayon::prelude::*;
-use std::sync::atomic::{AtomicUsize, Ordering};
 use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub struct EmailSearchTab {
     emails: Vec<String>,
     folder_path: Option<PathBuf>,
     email_list_path: Option<PathBuf>,
     search_in_progress: bool,
-    progress: Arc<(AtomicUsize, AtomicUsize)>, // (processed, total)
-    log_receiver: mpsc::Receiver<String>,
-    log_sender: mpsc::Sender<String>,
+    progress: Arc<Progress>,
     // Removed: search_results: String,
+    output_path: Option<PathBuf>,
     results_file_path: Option<PathBuf>,
+    not_found_file_path: Option<PathBuf>,
     found_emails: Arc<Mutex<HashSet<String>>>,
+    matches: Arc<Mutex<Vec<SearchMatch>>>,
+    results_table: ResultsTable,
+    control: Arc<JobControl>,
+    settings: EmailSearchSettings,
+    presets: PresetPicker,
+    previous_search: Option<SearchState>,
+    status: TabStatus,
 }
 
-impl EmailSearchTab {
-    pub fn new() -> Self {
-        let (log_sender, log_receiver) = channel();
+// Search settings as entered in the tab
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailSearchSettings {
+    pub query_mode: QueryMode,
+    pub domains: String,
+    pub pattern: String,
+    pub encoding: InputEncoding,
+    pub dialect: DialectOptions,
+    pub match_mode: MatchMode,
+    pub max_distance: usize,
+    pub first_match_only: bool,
+    pub file_types: String,
+    pub columns: String,
+    pub json_fields: String,
+    pub include_globs: String,
+    pub exclude_globs: String,
+    pub limit_depth: bool,
+    pub max_depth: usize,
+    pub follow_symlinks: bool,
+    pub include_hidden: bool,
+    pub threads: usize, // 0 means one per CPU core
+    pub memory_budget_mb: usize,
+    // Searched files are hashed for the history only when asked, as that reads each one again
+    pub hash_files: bool,
+}
+
+impl Default for EmailSearchSettings {
+    fn default() -> Self {
         Self {
+            query_mode: QueryMode::EmailList,
+            domains: String::new(),
+            pattern: String::new(),
+            encoding: InputEncoding::Auto,
+            dialect: DialectOptions::default(),
+            match_mode: MatchMode::Exact,
+            max_distance: 2,
+            first_match_only: true,
+            file_types: "csv".to_string(),
+            columns: "1,3".to_string(),
+            json_fields: String::new(),
+            include_globs: String::new(),
+            exclude_globs: String::new(),
+            limit_depth: false,
+            max_depth: 5,
+            follow_symlinks: false,
+            include_hidden: false,
+            threads: 0,
+            memory_budget_mb: 1024,
+            hash_files: false,
+        }
+    }
+}
+
+// The settings plus the chosen paths, for presets and for restoring the tab on launch
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailSearchPreset {
+    pub folder: Option<PathBuf>,
+    pub email_list: Option<PathBuf>,
+    pub results_file: Option<PathBuf>,
+    pub settings: EmailSearchSettings,
+}
+
+// Settings captured when a search starts and shared by the worker threads
+struct SearchOptions {
+    encoding: InputEncoding,
+    dialect: DialectOptions,
+    // Stop reporting an email from the list once it has been found
+    first_match_only: bool,
+    extensions: Vec<String>,
+    columns: Vec<usize>, // zero-based, empty means every column
+    json_fields: Vec<String>,
+    walk: WalkOptions,
+    // Files written by the search itself, which must not be scanned
+    excluded_files: Vec<PathBuf>,
+    hash_files: bool,
+}
+
+impl SearchOptions {
+    fn accepts(&self, name: &str) -> bool {
+        let extensions: Vec<&str> = self.extensions.iter().map(String::as_str).collect();
+        archive::has_extension(name, &extensions) && FileFormat::from_name(name).is_some()
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        // Compare names first so only likely candidates are canonicalized
+        self.excluded_files.iter().any(|excluded| {
+            excluded.file_name() == path.file_name()
+                && path.canonicalize().is_ok_and(|canonical| &canonical == excluded)
+        })
+    }
+}
+
+// Results and not-found lists of earlier searches, recognised by their header. Searching them
+// again would report every earlier hit as a new match.
+fn is_results_file(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    if !archive::has_extension(&name, &["csv"]) {
+        return false;
+    }
+    let mut first_line = String::new();
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    if BufReader::new(file.take(1024)).read_line(&mut first_line).is_err() {
+        return false;
+    }
+    let first_line = first_line.trim_end_matches(['\r', '\n']);
+    first_line.starts_with("query_email,file_path,") || (first_line == "query_email" && name.ends_with("_not_found.csv"))
+}
+
+fn absolute_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| std::env::current_dir().unwrap_or_default().join(path))
+}
+
+// Timestamped so a new search never overwrites the results of an earlier one
+fn default_results_file_name() -> String {
+    format!("search_results_{}.csv", Local::now().format("%Y%m%d_%H%M%S"))
+}
+
+fn split_list(text: &str) -> Vec<String> {
+    text.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+}
+
+impl EmailSearchTab {
+    pub fn new(preset: EmailSearchPreset) -> Self {
+        let mut tab = Self {
             emails: Vec::new(),
             folder_path: None,
             email_list_path: None,
             search_in_progress: false,
-            progress: Arc::new((AtomicUsize::new(0), AtomicUsize::new(0))),
-            log_receiver,
-            log_sender,
+            progress: Arc::new(Progress::new()),
             // Removed: search_results: String::new(),
+            output_path: None,
             results_file_path: None,
+            not_found_file_path: None,
             found_emails: Arc::new(Mutex::new(HashSet::new())),
+            matches: Arc::new(Mutex::new(Vec::new())),
+            results_table: ResultsTable::new(),
+            control: Arc::new(JobControl::new()),
+            settings: EmailSearchSettings::default(),
+            presets: PresetPicker::new("email_search"),
+            previous_search: SearchState::load(Path::new(STATE_FILE)),
+            status: TabStatus::new(),
+        };
+        tab.apply_preset(preset);
+        tab
+    }
+
+    pub fn preset(&self) -> EmailSearchPreset {
+        EmailSearchPreset {
+            folder: self.folder_path.clone(),
+            email_list: self.email_list_path.clone(),
+            results_file: self.output_path.clone(),
+            settings: self.settings.clone(),
+        }
+    }
+
+    pub fn pipeline_step(&self) -> Operation {
+        Operation::EmailSearch {
+            inputs: self.folder_path.iter().cloned().collect(),
+            email_list: self.email_list_path.clone().filter(|_| self.settings.query_mode == QueryMode::EmailList),
+            filters: Box::new(self.settings.clone()),
+            outputs: SearchOutputs { results_file: self.output_path.clone() },
         }
     }
 
-    pub fn ui(&mut self, ui: &mut egui::Ui, processing_status: &mut String, tx: &Sender<String>) {
+    pub fn load_step(&mut self, operation: &Operation) {
+        let Operation::EmailSearch { inputs, email_list, filters, outputs } = operation else {
+            return;
+        };
+        self.apply_preset(EmailSearchPreset {
+            folder: inputs.first().cloned(),
+            email_list: email_list.clone(),
+            results_file: outputs.results_file.clone(),
+            settings: (**filters).clone(),
+        });
+    }
+
+    fn apply_preset(&mut self, preset: EmailSearchPreset) {
+        self.folder_path = preset.folder;
+        self.email_list_path = preset.email_list;
+        self.output_path = preset.results_file;
+        self.settings = preset.settings;
+        self.emails.clear();
+        self.load_emails();
+    }
+
+    pub fn handle_event(&mut self, event: EventKind) {
+        self.status.apply(event);
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, tx: &Sender<Event>) {
+        let mut preset = self.preset();
+        if self.presets.ui(ui, &mut preset) {
+            self.apply_preset(preset);
+        }
+
         // UI elements and button handling...
         ui.horizontal(|ui| {
-            if ui.button("Select Email List").clicked() {
-                if let Some(file_path) = FileDialog::new().add_filter("Text file", &["txt"]).pick_file() {
-                    self.email_list_path = Some(file_path);
-                    self.load_emails();
-                }
+            ui.label("Search for:");
+            egui::ComboBox::from_id_source("email_search_query_mode")
+                .selected_text(self.settings.query_mode.label())
+                .show_ui(ui, |ui| {
+                    for mode in QueryMode::ALL {
+                        ui.selectable_value(&mut self.settings.query_mode, mode, mode.label());
+                    }
+                });
+        });
+        match self.settings.query_mode {
+            QueryMode::EmailList => {
+                ui.horizontal(|ui| {
+                    if ui.button("Select Email List").clicked() {
+                        if let Some(file_path) = FileDialog::new().add_filter("Text file", &["txt"]).pick_file() {
+                            self.email_list_path = Some(file_path);
+                            self.load_emails();
+                        }
+                    }
+                    if let Some(path) = &self.email_list_path {
+                        ui.label(format!("Selected email list: {}", path.display()));
+                    }
+                });
             }
-            if let Some(path) = &self.email_list_path {
-                ui.label(format!("Selected email list: {}", path.display()));
+            QueryMode::Domains => {
+                ui.horizontal(|ui| {
+                    ui.label("Domains:");
+                    ui.add(egui::TextEdit::singleline(&mut self.settings.domains).hint_text("acme.com, example.org"));
+                });
             }
-        });
+            QueryMode::Regex => {
+                ui.horizontal(|ui| {
+                    ui.label("Pattern:");
+                    ui.add(egui::TextEdit::singleline(&mut self.settings.pattern).hint_text(r"sales\.\w+@acme\.com"));
+                });
+            }
+        }
 
         ui.horizontal(|ui| {
             if ui.button("Select Folder").clicked() {
@@ -67,67 +299,162 @@ impl EmailSearchTab {
                 ui.label(format!("Selected folder: {}", path.display()));
             }
         });
-        if ui.button("Search").clicked() && !self.search_in_progress {
-            if let (Some(folder), Some(_)) = (&self.folder_path, &self.email_list_path) {
-                let emails = Arc::new(self.emails.clone());
-                let folder_path = Arc::new(folder.clone());
-                let progress = self.progress.clone();
-                let log_tx = tx.clone();
-                
-                // Create results file
-                let results_file_path = folder.join("search_results.csv");
-                self.results_file_path = Some(results_file_path.clone());
-                
-                let found_emails = self.found_emails.clone();
-                
-                match create_results_file(&results_file_path) {
-                    Ok(file) => {
-                        let results_file = Arc::new(Mutex::new(file));
-
-                        self.search_in_progress = true;
-                        *processing_status = "Search in progress...".to_string();
-
-                        thread::spawn(move || {
-                            let total_emails = emails.len();
-                            progress.1.store(total_emails, Ordering::Relaxed);
-
-                            emails.par_iter().enumerate().for_each(|(index, email)| {
-                                if let Err(e) = search_email_main(email, &folder_path, log_tx.clone(), results_file.clone(), progress.clone(), index, found_emails.clone()) {
-                                    log_tx.send(format!("Error searching email {}: {}", email, e)).unwrap();
-                                }
-                            });
-
-                            log_tx.send("Search completed.".to_string()).unwrap();
-                        });
-                    },
-                    Err(e) => {
-                        *processing_status = format!("Error creating results file: {}", e);
+
+        ui.horizontal(|ui| {
+            if ui.button("Select Results File").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("CSV", &["csv"])
+                    .set_file_name(&default_results_file_name())
+                    .save_file()
+                {
+                    self.output_path = Some(path);
+                }
+            }
+            match &self.output_path {
+                Some(path) => {
+                    ui.label(format!("Results file: {}", path.display()));
+                    if ui.small_button("Use default").clicked() {
+                        self.output_path = None;
                     }
                 }
-            } else {
-                *processing_status = "Please select a folder and email list first".to_string();
+                None => {
+                    ui.label("Results file: timestamped file in the current folder");
+                }
             }
+        });
+        encoding::encoding_selector(ui, "email_search_encoding", &mut self.settings.encoding);
+        dialect::dialect_selector(ui, "email_search_dialect", &mut self.settings.dialect, true);
+        ui.add_enabled_ui(self.settings.query_mode == QueryMode::EmailList, |ui| ui.horizontal(|ui| {
+            ui.label("Match:");
+            egui::ComboBox::from_id_source("email_search_match_mode")
+                .selected_text(self.settings.match_mode.label())
+                .show_ui(ui, |ui| {
+                    for mode in MatchMode::ALL {
+                        ui.selectable_value(&mut self.settings.match_mode, mode, mode.label());
+                    }
+                });
+            if self.settings.match_mode == MatchMode::Fuzzy {
+                ui.label("Max edits:");
+                ui.add(egui::DragValue::new(&mut self.settings.max_distance).clamp_range(1..=5));
+            }
+            ui.checkbox(&mut self.settings.first_match_only, "Only the first match per email");
+        }));
+        ui.horizontal(|ui| {
+            ui.label("File types:");
+            ui.add(egui::TextEdit::singleline(&mut self.settings.file_types).hint_text("csv, tsv, txt, json, ndjson, mbox, eml"));
+        });
+        ui.horizontal(|ui| {
+            ui.label("CSV/TSV columns:");
+            ui.add(egui::TextEdit::singleline(&mut self.settings.columns).hint_text("1, 3 (empty for all)"));
+        });
+        ui.horizontal(|ui| {
+            ui.label("JSON fields:");
+            ui.add(egui::TextEdit::singleline(&mut self.settings.json_fields).hint_text("email, contact.email (empty for all)"));
+        });
+        ui.collapsing("Folder filters", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Include:");
+                ui.add(egui::TextEdit::singleline(&mut self.settings.include_globs).hint_text("*.csv, exports/**"));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Exclude:");
+                ui.add(egui::TextEdit::singleline(&mut self.settings.exclude_globs).hint_text("backup, node_modules, *.tmp"));
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.settings.limit_depth, "Max depth:");
+                ui.add_enabled(self.settings.limit_depth, egui::DragValue::new(&mut self.settings.max_depth).clamp_range(0..=100));
+            });
+            ui.checkbox(&mut self.settings.follow_symlinks, "Follow symbolic links");
+            ui.checkbox(&mut self.settings.include_hidden, "Include hidden files and folders");
+        });
+        ui.collapsing("Performance", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Threads:");
+                ui.add(egui::DragValue::new(&mut self.settings.threads).clamp_range(0..=256));
+                ui.label("(0 = one per CPU core)");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Memory budget (MB):");
+                ui.add(egui::DragValue::new(&mut self.settings.memory_budget_mb).clamp_range(64..=65536));
+            });
+            ui.checkbox(&mut self.settings.hash_files, "Record a SHA-256 of each searched file in the history")
+                .on_hover_text("Reads every file a second time; otherwise only sizes and modification times are recorded");
+        });
+        // The worker marks the job finished once it has stopped, whether it completed or was cancelled
+        if self.search_in_progress && self.control.is_finished() {
+            self.search_in_progress = false;
+            // A cancelled search leaves its state behind so it can be resumed
+            self.previous_search = SearchState::load(Path::new(STATE_FILE));
         }
 
-        // Display logs
-        while let Ok(log) = self.log_receiver.try_recv() {
-            ui.label(log);
+        // Offer to pick up a search that was cancelled or interrupted by closing the app
+        let (mut resume, mut discard) = (false, false);
+        if let (Some(state), false) = (&self.previous_search, self.search_in_progress) {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "Previous search of {}: {}/{} files done",
+                    state.folder.display(),
+                    state.completed_files.len(),
+                    state.total_files
+                ));
+                resume = ui.button("Resume previous search").clicked();
+                discard = ui.button("Discard").clicked();
+            });
+        }
+        if discard {
+            SearchState::remove(Path::new(STATE_FILE));
+            self.previous_search = None;
         }
+        if resume {
+            let list_mode = |state: &SearchState| state.settings.query_mode == QueryMode::EmailList;
+            if let Some(state) = self.previous_search.take().filter(|state| !list_mode(state) || state.email_list.exists()) {
+                self.folder_path = Some(state.folder.clone());
+                self.settings = state.settings.clone();
+                if list_mode(&state) {
+                    self.email_list_path = Some(state.email_list.clone());
+                    self.load_emails();
+                }
+                match self.start_search(tx, Some(state)) {
+                    Ok(()) => self.status.set("Resuming search..."),
+                    Err(message) => self.status.set(message),
+                }
+            } else {
+                self.status.set("The email list of the previous search no longer exists");
+            }
+        }
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!self.search_in_progress, egui::Button::new("Search")).clicked() {
+                match self.start_search(tx, None) {
+                    Ok(()) => self.status.set("Search in progress..."),
+                    Err(message) => self.status.set(message),
+                }
+            }
+            if self.search_in_progress {
+                if self.control.is_paused() {
+                    if ui.button("▶ Resume").clicked() {
+                        self.control.resume();
+                        self.status.set("Search in progress...");
+                    }
+                } else if ui.button("⏸ Pause").clicked() {
+                    self.control.pause();
+                    self.status.set("Search paused");
+                }
+                if !self.control.is_cancelled() && ui.button("⏹ Cancel").clicked() {
+                    self.control.cancel();
+                    self.status.set("Cancelling search...");
+                }
+            }
+        });
 
         if self.search_in_progress {
-            let processed = self.progress.0.load(Ordering::Relaxed);
-            let total = self.progress.1.load(Ordering::Relaxed);
-            ui.add(egui::ProgressBar::new(processed as f32 / total as f32)
-                .text(format!("Processed: {}/{}", processed, total)));
+            progress::progress_panel(ui, &self.progress);
+            // Keep polling the worker state even when no messages arrive
+            ui.ctx().request_repaint_after(Duration::from_millis(250));
         }
 
         // Display processing status
-        if !processing_status.is_empty() {
-            ui.horizontal(|ui| {
-                ui.label(RichText::new("Status:").strong());
-                ui.label(&*processing_status);
-            });
-        }
+        self.status.ui(ui);
         // Display results file path
         if let Some(path) = &self.results_file_path {
             ui.horizontal(|ui| {
@@ -135,102 +462,607 @@ impl EmailSearchTab {
                 ui.label(path.display().to_string());
             });
         }
+        if let Some(path) = &self.not_found_file_path {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("Not found file:").strong());
+                ui.label(path.display().to_string());
+            });
+        }
+
+        // Browse the matches found so far
+        let matches = self.matches.clone();
+        let matches = matches.lock().unwrap();
+        if !matches.is_empty() {
+            ui.separator();
+            if let Some(message) = self.results_table.ui(ui, &matches) {
+                self.status.set(message);
+            }
+        }
+    }
+
+    // Starts a new search, or continues `resume` by skipping the files it already completed
+    fn start_search(&mut self, tx: &Sender<Event>, resume: Option<SearchState>) -> Result<(), String> {
+        let emails = self.email_list_path.is_some().then_some(self.emails.as_slice());
+        let job = SearchJob::new(&self.preset(), emails, resume, Some(Path::new(STATE_FILE)), EventSender::new(TabId::EmailSearch, tx))?;
+        self.results_file_path = Some(job.results_file.clone());
+        self.not_found_file_path = Some(job.not_found_path.clone());
+        self.progress = job.context.progress.clone();
+        self.found_emails = job.context.found_emails.clone();
+        self.matches = job.context.matches.clone();
+        self.control = job.context.control.clone();
+        self.results_table.reset();
+        self.previous_search = None;
+        self.search_in_progress = true;
+        let (events, progress) = (job.context.events.clone(), self.progress.clone());
+        thread::spawn(move || {
+            // The job is marked finished as the panic unwinds; the tab still has to be told
+            if panic::catch_unwind(AssertUnwindSafe(|| job.run())).is_err() {
+                progress.finish();
+                events.error("The search stopped because of an internal error");
+            }
+        });
+        Ok(())
     }
 
+    // Runs a search from the history again. The results go to a new file so the earlier ones are kept.
+    pub fn rerun(&mut self, job: &JobRecord, tx: &Sender<Event>) {
+        let JobSettings::EmailSearch { folder, email_list, settings, .. } = &job.settings else {
+            return;
+        };
+        if self.search_in_progress {
+            self.status.set("A search is already running");
+            return;
+        }
+        if let Some(path) = email_list.as_ref().filter(|path| !path.exists()) {
+            self.status.error(format!("The email list {} no longer exists", path.display()));
+            return;
+        }
+        self.folder_path = Some(folder.clone());
+        self.email_list_path = email_list.clone();
+        self.settings = (**settings).clone();
+        self.output_path = None;
+        self.load_emails();
+        match self.start_search(tx, None) {
+            Ok(()) => self.status.set("Search in progress..."),
+            Err(message) => self.status.set(message),
+        }
+    }
 
+    // A preset or the last session may point to a list that has since been moved
     fn load_emails(&mut self) {
         if let Some(path) = &self.email_list_path {
-            let file = File::open(path).expect("Failed to open email list file");
-            let reader = BufReader::new(file);
-            self.emails = reader.lines().filter_map(Result::ok).collect();
+            match read_email_list(path, self.settings.encoding) {
+                Ok(emails) => self.emails = emails,
+                Err(e) => {
+                    self.emails.clear();
+                    self.status.error(format!("Could not read the email list {}: {}", path.display(), e));
+                }
+            }
+        }
+    }
+}
+
+pub fn read_email_list(path: &Path, encoding: InputEncoding) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let (input, _) = encoding::open_file(path, encoding)?;
+    Ok(BufReader::new(input).lines().map_while(Result::ok).collect())
+}
+
+// `emails` is None when no list has been chosen, which only a list search minds
+fn build_query(settings: &EmailSearchSettings, emails: Option<&[String]>) -> Result<SearchQuery, String> {
+    match settings.query_mode {
+        QueryMode::EmailList => {
+            let emails = emails.ok_or("Please select a folder and email list first")?;
+            ListMatcher::new(emails, settings.match_mode, settings.max_distance)
+                .map(SearchQuery::List)
+                .map_err(|e| format!("Could not build the email matcher: {}", e))
+        }
+        QueryMode::Domains => {
+            let domains = split_list(&settings.domains);
+            if domains.is_empty() {
+                return Err("Please enter at least one domain".to_string());
+            }
+            Ok(SearchQuery::domains(&domains))
         }
+        QueryMode::Regex => {
+            if settings.pattern.trim().is_empty() {
+                return Err("Please enter a regex pattern".to_string());
+            }
+            SearchQuery::regex(settings.pattern.trim()).map_err(|e| format!("Invalid regex pattern: {}", e))
+        }
+    }
+}
+
+fn search_options(settings: &EmailSearchSettings) -> Result<SearchOptions, globset::Error> {
+    let mut walk = WalkOptions::new(&split_list(&settings.include_globs), &split_list(&settings.exclude_globs))?;
+    walk.max_depth = settings.limit_depth.then_some(settings.max_depth);
+    walk.symlinks = if settings.follow_symlinks { SymlinkPolicy::Follow } else { SymlinkPolicy::Skip };
+    walk.include_hidden = settings.include_hidden;
+
+    Ok(SearchOptions {
+        encoding: settings.encoding,
+        dialect: settings.dialect,
+        first_match_only: settings.query_mode == QueryMode::EmailList && settings.first_match_only,
+        extensions: split_list(&settings.file_types).into_iter().map(|ext| ext.trim_start_matches('.').to_lowercase()).collect(),
+        columns: split_list(&settings.columns).iter().filter_map(|c| c.parse::<usize>().ok()).filter(|&c| c > 0).map(|c| c - 1).collect(),
+        json_fields: split_list(&settings.json_fields),
+        walk,
+        excluded_files: Vec::new(),
+        hash_files: settings.hash_files,
+    })
+}
+
+// A search that is ready to run, from the tab or from a pipeline
+pub struct SearchJob {
+    folder: PathBuf,
+    email_list: Option<PathBuf>,
+    results_file: PathBuf,
+    not_found_path: PathBuf,
+    query: SearchQuery,
+    pool: rayon::ThreadPool,
+    job_settings: JobSettings,
+    // Recorded instead of the email list when set
+    inputs: Option<Vec<InputFile>>,
+    // Written by a larger job before the search, recorded with the search's own
+    earlier_outputs: Vec<OutputFile>,
+    context: SearchContext,
+}
+
+impl SearchJob {
+    // Opens the results file and saves the initial state to `state_file`. `resume` continues an
+    // earlier search by skipping the files it already completed. Without a state file, e.g. in a
+    // pipeline, the search cannot be resumed and leaves the tab's saved search alone.
+    pub fn new(
+        preset: &EmailSearchPreset,
+        emails: Option<&[String]>,
+        resume: Option<SearchState>,
+        state_file: Option<&Path>,
+        events: EventSender,
+    ) -> Result<Self, String> {
+        let settings = &preset.settings;
+        let folder = preset.folder.clone().ok_or("Please select a folder first")?;
+        let query = build_query(settings, emails)?;
+        let mut options = search_options(settings).map_err(|e| format!("Invalid folder filter: {}", e))?;
+        let resuming = resume.is_some();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(settings.threads)
+            .build()
+            .map_err(|e| format!("Could not start the search threads: {}", e))?;
+
+        // Create results file, or keep appending to it when resuming
+        let results_file = match (&resume, &preset.results_file) {
+            (Some(state), _) => state.results_file.clone(),
+            (None, Some(path)) => path.clone(),
+            (None, None) => std::env::current_dir().unwrap_or_default().join(default_results_file_name()),
+        };
+        let results_writer = open_results_writer(&results_file, resuming).map_err(|e| format!("Error creating results file: {}", e))?;
+        let not_found_path = not_found_file_path(&results_file);
+
+        // The results may be written inside the searched folder, so keep the search from reading its own output
+        options.excluded_files = [Some(results_file.as_path()), Some(not_found_path.as_path()), state_file]
+            .iter()
+            .flatten()
+            .map(|path| absolute_path(path))
+            .collect();
+
+        let email_list = (settings.query_mode == QueryMode::EmailList).then(|| preset.email_list.clone()).flatten();
+        let job_settings = JobSettings::EmailSearch {
+            folder: folder.clone(),
+            email_list: email_list.clone(),
+            results_file: results_file.clone(),
+            settings: Box::new(settings.clone()),
+        };
+        let state = resume.unwrap_or_else(|| SearchState {
+            folder: folder.clone(),
+            email_list: preset.email_list.clone().unwrap_or_default(),
+            results_file: results_file.clone(),
+            settings: settings.clone(),
+            total_files: 0,
+            completed_files: HashSet::new(),
+            found_emails: HashSet::new(),
+            completed_inputs: Vec::new(),
+        });
+
+        // A resumed search keeps showing what it found before
+        let previous_matches = if resuming { results_table::load_matches(&state.results_file).unwrap_or_default() } else { Vec::new() };
+        let recorded = previous_matches.iter().map(match_key).collect();
+        let context = SearchContext {
+            files: Vec::new(),
+            options,
+            control: Arc::new(JobControl::new()),
+            budget: MemoryBudget::new(settings.memory_budget_mb.max(1) * 1024 * 1024),
+            events,
+            errors: AtomicUsize::new(0),
+            results_writer: Mutex::new(results_writer),
+            progress: Arc::new(Progress::new()),
+            found_emails: Arc::new(Mutex::new(state.found_emails.clone())),
+            matches: Arc::new(Mutex::new(previous_matches)),
+            recorded,
+            state: Mutex::new(state),
+            state_file: state_file.map(Path::to_path_buf),
+            last_saved: Mutex::new(Instant::now()),
+        };
+        context.save_state();
+
+        Ok(Self { folder, email_list, results_file, not_found_path, query, pool, job_settings, inputs: None, earlier_outputs: Vec::new(), context })
+    }
+
+    // Records the search in the history as part of a larger job, e.g. a pipeline that ends with it
+    pub fn record_as(mut self, settings: JobSettings, inputs: Vec<InputFile>, outputs: Vec<OutputFile>) -> Self {
+        self.job_settings = settings;
+        self.inputs = Some(inputs);
+        self.earlier_outputs = outputs;
+        self
+    }
+
+    pub fn progress(&self) -> Arc<Progress> {
+        self.context.progress.clone()
+    }
+
+    pub fn control(&self) -> Arc<JobControl> {
+        self.context.control.clone()
+    }
+
+    pub fn matches(&self) -> Arc<Mutex<Vec<SearchMatch>>> {
+        self.context.matches.clone()
+    }
+
+    // Walks the folder once, then searches each file once for the whole query
+    pub fn run(self) {
+        let SearchJob { folder, email_list, results_file, not_found_path, query, pool, job_settings, inputs, earlier_outputs, mut context } = self;
+        let started = Instant::now();
+        let _finished = context.control.finish_on_drop();
+        context.events.started(format!("Scanning folder: {}", folder.display()));
+        let mut inputs = inputs.unwrap_or_else(|| email_list.iter().map(|path| InputFile::hash(path)).collect());
+        if absolute_path(&results_file).starts_with(absolute_path(&folder)) {
+            context.events.warning(format!("The results file {} is inside the searched folder. It is not searched, and neither are earlier results files there.", results_file.display()));
+        }
+        let options = &context.options;
+        let (files, warnings) = walk::collect_files(&folder, &options.walk, &|path| {
+            !options.is_excluded(path) && (archive::is_archive(path) || (options.accepts(&path.to_string_lossy()) && !is_results_file(path)))
+        });
+        for warning in warnings {
+            context.events.warning(warning);
+        }
+        context.files = files;
+        context.events.progress(format!("Searching {} files...", context.files.len()));
+
+        let completed = {
+            let mut state = context.state.lock().unwrap();
+            state.total_files = context.files.len();
+            state.completed_files.clone()
+        };
+        context.progress.start(&context.files);
+        let files: Vec<&PathBuf> = context.files.iter().filter(|path| !completed.contains(*path)).collect();
+        for path in context.files.iter().filter(|path| completed.contains(*path)) {
+            context.progress.skip_file(path);
+        }
+
+        pool.install(|| {
+            files.par_iter().for_each(|path| {
+                if !context.control.checkpoint() {
+                    return;
+                }
+                match search_file(path, &query, &context) {
+                    // A file interrupted by cancellation is searched again on resume
+                    Ok(()) if !context.control.is_cancelled() => context.complete_file(path),
+                    Ok(()) => {}
+                    Err(e) => {
+                        context.file_error(path, format!("Error searching file {}: {}", path.display(), e));
+                    }
+                }
+                context.progress.file_done();
+            });
+        });
+        if let Err(e) = context.flush_results() {
+            context.error(format!("Error writing results: {}", e));
+        }
+        // The results also hold what was found in the files searched before a resume. States saved
+        // before the inputs were kept only have the paths of those files.
+        let mut searched = context.state.lock().unwrap().completed_inputs.clone();
+        let recorded: HashSet<PathBuf> = searched.iter().map(|input| input.path.clone()).collect();
+        searched.extend(completed.iter().filter(|path| !recorded.contains(*path)).map(|path| InputFile::stat(path)));
+        searched.sort_by(|a, b| a.path.cmp(&b.path));
+        inputs.extend(searched);
+
+        let mut outputs = earlier_outputs;
+        outputs.push(OutputFile { path: results_file, rows: context.matches.lock().unwrap().len() as u64 });
+        let message = if context.control.is_cancelled() {
+            context.save_state();
+            if context.state_file.is_some() { "Search cancelled. It can be resumed later." } else { "Search cancelled." }.to_string()
+        } else {
+            if let Some(path) = &context.state_file {
+                SearchState::remove(path);
+            }
+            match query.email_list() {
+                Some(all_emails) => {
+                    let found = context.found_emails.lock().unwrap().clone();
+                    match write_not_found(&not_found_path, all_emails, &found) {
+                        Ok(count) => {
+                            outputs.push(OutputFile { path: not_found_path.clone(), rows: count as u64 });
+                            format!("Search completed. {} emails not found.", count)
+                        }
+                        Err(e) => {
+                            context.error(format!("The not-found list could not be written: {}", e));
+                            "Search completed".to_string()
+                        }
+                    }
+                }
+                None => format!("Search completed. {} matching addresses found.", context.found_emails.lock().unwrap().len()),
+            }
+        };
+        context.events.finished(Summary {
+            message,
+            files: context.files.len(),
+            errors: context.errors.load(Ordering::Relaxed),
+            elapsed: started.elapsed(),
+            details: JobDetails { settings: job_settings, inputs, outputs, rows: context.progress.snapshot().rows },
+        });
+        context.progress.finish();
     }
 }
 
-fn create_results_file(path: &Path) -> Result<File, std::io::Error> {
+// Writes the header unless appending to a results file that already has rows
+fn open_results_writer(path: &Path, append: bool) -> Result<csv::Writer<File>, Box<dyn std::error::Error>> {
     let file = OpenOptions::new()
         .write(true)
         .create(true)
-        .truncate(true)
+        .append(append)
+        .truncate(!append)
         .open(path)?;
-    Ok(file)
+    let is_empty = file.metadata()?.len() == 0;
+    let mut writer = csv::Writer::from_writer(file);
+    if is_empty {
+        writer.write_record(RESULTS_HEADER)?;
+        writer.flush()?;
+    }
+    Ok(writer)
 }
 
-fn search_email_in_folder(
-    email: &str, 
-    folder_path: &Path, 
-    log_tx: Sender<String>, 
-    results_file: Arc<Mutex<File>>,
-    found_emails: Arc<Mutex<HashSet<String>>>
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Check if email has already been found
-    if found_emails.lock().unwrap().contains(email) {
-        return Ok(());
-    }
-
-    log_tx.send(format!("Searching in folder: {}", folder_path.display()))?;
-
-    for entry in fs::read_dir(folder_path)? {
-        let entry = entry?;
-        let path = entry.path();
-        
-        if path.is_dir() {
-            search_email_in_folder(email, &path, log_tx.clone(), results_file.clone(), found_emails.clone())?;
-        } else if path.is_file() && path.extension().map_or(false, |ext| ext == "csv") {
-            log_tx.send(format!("Searching file: {}", path.display()))?;
-
-            let file = File::open(&path)?;
-            let transcoded = DecodeReaderBytesBuilder::new()
-                .encoding(Some(WINDOWS_1252))
-                .utf8_passthru(true)
-                .build(file);
-            let reader = BufReader::new(transcoded);
-
-            let mut email_found = false;
-            reader.lines()
-                .enumerate()
-                .filter_map(|(row_index, line_result)| line_result.ok().map(|line| (row_index, line)))
-                .filter(|(_, line)| {
-                    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
-                    fields.len() > 2 && (fields[0].eq_ignore_ascii_case(email) || fields[2].eq_ignore_ascii_case(email))
-                })
-                .for_each(|(row_index, line)| {
-                    if !email_found {
-                        let result = format!("{},{}\n", row_index + 1, line);
-                        if let Err(e) = results_file.lock().unwrap().write_all(result.as_bytes()) {
-                            log_tx.send(format!("Error writing result: {}", e)).unwrap();
-                        } else {
-                            log_tx.send(format!("Match found: {}", result)).unwrap();
-                            email_found = true;
-                            found_emails.lock().unwrap().insert(email.to_string());
-                        }
-                    }
-                });
+// `search_results.csv` -> `search_results_not_found.csv`
+fn not_found_file_path(results_path: &Path) -> PathBuf {
+    let stem = results_path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    results_path.with_file_name(format!("{}_not_found.csv", stem))
+}
 
-            if email_found {
-                return Ok(());
-            }
+fn write_not_found(path: &Path, emails: &[String], found: &HashSet<String>) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["query_email"])?;
+    let mut count = 0;
+    for email in emails.iter().filter(|email| !found.contains(*email)) {
+        writer.write_record([email])?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+// How often the search state is written to disk while a search runs
+const STATE_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+// Everything the workers share while searching
+struct SearchContext {
+    files: Vec<PathBuf>,
+    options: SearchOptions,
+    control: Arc<JobControl>,
+    budget: MemoryBudget,
+    events: EventSender,
+    errors: AtomicUsize,
+    results_writer: Mutex<csv::Writer<File>>,
+    progress: Arc<Progress>,
+    found_emails: Arc<Mutex<HashSet<String>>>,
+    matches: Arc<Mutex<Vec<SearchMatch>>>,
+    // The matches a resumed search had already written. A file that was interrupted part way is
+    // searched again, and must not add them twice.
+    recorded: HashSet<MatchKey>,
+    state: Mutex<SearchState>,
+    // Where the state is saved, when the search can be resumed
+    state_file: Option<PathBuf>,
+    last_saved: Mutex<Instant>,
+}
+
+// The email, file, record number and column of a match
+type MatchKey = (String, String, usize, String);
+
+fn match_key(search_match: &SearchMatch) -> MatchKey {
+    (search_match.email.clone(), search_match.file.clone(), search_match.record_number, search_match.column.clone())
+}
+
+impl SearchContext {
+    // Returns false when a resumed search had already written the match
+    fn write_match(&self, email: &str, source: &str, record: &formats::Record, column: &str, match_type: &str) -> Result<bool, csv::Error> {
+        let search_match = SearchMatch {
+            email: email.to_string(),
+            file: source.to_string(),
+            record_number: record.number,
+            column: column.to_string(),
+            match_type: match_type.to_string(),
+            record: record.text(),
+        };
+        if !self.recorded.is_empty() && self.recorded.contains(&match_key(&search_match)) {
+            return Ok(false);
         }
+        self.results_writer.lock().unwrap().write_record(search_match.fields())?;
+        self.matches.lock().unwrap().push(search_match);
+        Ok(true)
+    }
+
+    fn flush_results(&self) -> std::io::Result<()> {
+        self.results_writer.lock().unwrap().flush()
     }
 
+    fn error(&self, message: String) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        self.events.error(message);
+    }
+
+    fn file_error(&self, path: &Path, message: String) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        self.events.file_error(&path.display().to_string(), message);
+    }
+
+    fn complete_file(&self, path: &Path) {
+        // Hashed straight after the search, while the file is likely still cached
+        let input = if self.options.hash_files { InputFile::hash(path) } else { InputFile::stat(path) };
+        let mut state = self.state.lock().unwrap();
+        state.completed_files.insert(path.to_path_buf());
+        state.completed_inputs.push(input);
+        drop(state);
+        self.save_state_if_due();
+    }
+
+    fn save_state_if_due(&self) {
+        let due = self.last_saved.lock().unwrap().elapsed() >= STATE_SAVE_INTERVAL;
+        if due {
+            self.save_state();
+        }
+    }
+
+    // Writes out the results first, so the saved state never gets ahead of the results file
+    fn save_state(&self) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        if let Err(e) = self.flush_results() {
+            self.error(format!("Error writing results: {}", e));
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.found_emails = self.found_emails.lock().unwrap().clone();
+        if let Err(e) = state.save(path) {
+            self.error(format!("Error saving search state: {}", e));
+        }
+        *self.last_saved.lock().unwrap() = Instant::now();
+    }
+}
+
+const ROW_BATCH: u64 = 1024;
+
+// Files larger than this are split into chunks that are searched in parallel
+const CHUNK_SIZE: usize = 32 * 1024 * 1024;
+
+fn search_file(path: &Path, query: &SearchQuery, context: &SearchContext) -> Result<(), Box<dyn std::error::Error>> {
+    let SearchContext { options, control, budget, progress, .. } = context;
+    progress.set_current_file(&path.display().to_string());
+
+    let name = path.to_string_lossy();
+    let format = FileFormat::from_name(&name);
+    let size = fs::metadata(path)?.len() as usize;
+    let splittable = matches!(format, Some(FileFormat::Csv | FileFormat::Tsv | FileFormat::Text | FileFormat::Ndjson));
+    // A contains search scans the raw bytes of every such file with its automaton, in one pass
+    let mapped = size > 2 * CHUNK_SIZE || (size > 0 && query.scans_bytes());
+    if splittable && mapped && !archive::is_archive(path) {
+        let file = File::open(path)?;
+        // Safety: the map is only read, and searched files are not expected to change during a search
+        let map = unsafe { Mmap::map(&file)? };
+        let (_, used) = encoding::decode_reader(&map[..], options.encoding)?;
+        // UTF-16 line breaks are two bytes wide, so the file cannot be cut on single bytes
+        if !matches!(used, InputEncoding::Utf16Le | InputEncoding::Utf16Be) {
+            return search_chunks(&name, &map, format.unwrap_or(FileFormat::Text), used, query, context);
+        }
+    }
+
+    // Formats that are parsed as a whole are held in memory entirely
+    let held = if matches!(format, Some(FileFormat::Json | FileFormat::Eml)) { size } else { 0 };
+    let _reservation = budget.reserve(held);
+    archive::visit_file(path, progress, &|name| options.accepts(name), &mut |name, input| {
+        if control.is_cancelled() {
+            return Ok(ControlFlow::Break(()));
+        }
+        let format = FileFormat::from_name(name).unwrap_or(FileFormat::Text);
+        let (mut input, _) = encoding::decode_reader(input, options.encoding)?;
+        search_records(name, format, &mut input, &options.dialect, 0, query, context)?;
+        Ok(ControlFlow::Continue(()))
+    })
+}
+
+// Searches a large file in record-aligned chunks of its memory map, holding at most the memory budget at once
+fn search_chunks(name: &str, data: &[u8], format: FileFormat, encoding: InputEncoding, query: &SearchQuery, context: &SearchContext) -> Result<(), Box<dyn std::error::Error>> {
+    // Later chunks have no header to detect the dialect from, so every chunk uses the file's
+    let dialect = if format.is_delimited() {
+        let (head, _) = encoding::decode_reader(data, encoding)?;
+        Some(dialect::detect(head, &formats::delimited_options(format, &context.options.dialect))?.1)
+    } else {
+        None
+    };
+    let dialect_options = dialect.map(|dialect| dialect.options()).unwrap_or_default();
+    let chunks = chunks::split_records(data, CHUNK_SIZE, dialect.as_ref());
+    chunks.par_iter().try_for_each(|chunk| {
+        if !context.control.checkpoint() {
+            return Ok(());
+        }
+        let _reservation = context.budget.reserve(chunk.end - chunk.start);
+        let bytes = &data[chunk.start..chunk.end];
+        // Most chunks hold none of a long list, and are ruled out without being parsed
+        if query.may_match(bytes) {
+            let (mut input, _) = encoding::decode_reader(bytes, encoding).map_err(|e| e.to_string())?;
+            search_records(name, format, &mut input, &dialect_options, chunk.first_record - 1, query, context).map_err(|e| e.to_string())?;
+        } else {
+            context.progress.add_rows(chunk.records as u64);
+        }
+        context.progress.add_bytes((chunk.end - chunk.start) as u64);
+        Ok::<(), String>(())
+    })?;
     Ok(())
 }
 
-fn search_email_main(
-    email: &str, 
-    folder_path: &Arc<PathBuf>, 
-    log_tx: mpsc::Sender<String>, 
-    results_file: Arc<Mutex<File>>, 
-    progress: Arc<(AtomicUsize, AtomicUsize)>, 
-    index: usize,
-    found_emails: Arc<Mutex<HashSet<String>>>
-) -> Result<String, Box<dyn std::error::Error>> {
-    log_tx.send("Starting search...".to_string())?;
-    
-    search_email_in_folder(email, folder_path, log_tx.clone(), results_file, found_emails)?;
-    
-    // Update progress
-    progress.0.fetch_add(1, Ordering::Relaxed);
-    
-    Ok("Search completed successfully.".to_string())
-}
\ No newline at end of file
+// Reports every address in the records that the query matches. `first_record` offsets the
+// record numbers of a chunk that starts part way through a file.
+fn search_records(
+    name: &str,
+    format: FileFormat,
+    input: &mut dyn std::io::Read,
+    dialect_options: &DialectOptions,
+    first_record: usize,
+    query: &SearchQuery,
+    context: &SearchContext,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let SearchContext { options, control, events, found_emails, progress, .. } = context;
+    let use_columns = format.is_delimited() && !options.columns.is_empty();
+    // Rows are counted in batches to keep the shared counter cheap
+    let mut rows = 0;
+    let (mut bad_lines, mut first_bad_line) = (0, String::new());
+
+    let result = formats::read_records(format, input, &options.json_fields, dialect_options, &mut |mut record| {
+        record.number += first_record;
+        rows += 1;
+        if rows == ROW_BATCH {
+            progress.add_rows(rows);
+            rows = 0;
+        }
+        // An address is reported once per record, in the first column it appears in
+        let mut reported = HashSet::new();
+        for (column, field) in record.fields.iter().enumerate() {
+            if use_columns && !options.columns.contains(&column) {
+                continue;
+            }
+            for (address, match_type) in query.find(field) {
+                if !reported.insert(address.clone()) {
+                    continue;
+                }
+                let first = found_emails.lock().unwrap().insert(address.clone());
+                if !first && options.first_match_only {
+                    continue;
+                }
+                // Column numbers only mean something for delimited files
+                let column = if format.is_delimited() { (column + 1).to_string() } else { String::new() };
+                match context.write_match(&address, name, &record, &column, &match_type) {
+                    Ok(true) => events.found(&address, name, record.number),
+                    Ok(false) => {}
+                    Err(e) => context.error(format!("Error writing result: {}", e)),
+                }
+            }
+        }
+        control.checkpoint()
+    }, &mut |number, reason| {
+        if bad_lines == 0 {
+            first_bad_line = format!("line {}: {}", number + first_record, reason);
+        }
+        bad_lines += 1;
+    });
+    progress.add_rows(rows);
+    // One warning per file, however many lines are bad
+    if bad_lines > 0 {
+        events.warning(format!("{}: skipped {} lines that are not valid JSON, the first at {}", name, bad_lines, first_bad_line));
+    }
+    result
+}
@@ -0,0 +1,261 @@
+use eframe::egui;
+use encoding_rs::{Encoding, SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1251, WINDOWS_1252};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+// How many bytes are sniffed before choosing an encoding
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum InputEncoding {
+    #[default]
+    Auto,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1252,
+    Windows1251,
+    ShiftJis,
+    Latin1,
+}
+
+impl InputEncoding {
+    pub const ALL: [InputEncoding; 8] = [
+        InputEncoding::Auto,
+        InputEncoding::Utf8,
+        InputEncoding::Utf16Le,
+        InputEncoding::Utf16Be,
+        InputEncoding::Windows1252,
+        InputEncoding::Windows1251,
+        InputEncoding::ShiftJis,
+        InputEncoding::Latin1,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            InputEncoding::Auto => "Auto-detect",
+            InputEncoding::Utf8 => "UTF-8",
+            InputEncoding::Utf16Le => "UTF-16LE",
+            InputEncoding::Utf16Be => "UTF-16BE",
+            InputEncoding::Windows1252 => "Windows-1252",
+            InputEncoding::Windows1251 => "Windows-1251",
+            InputEncoding::ShiftJis => "Shift-JIS",
+            InputEncoding::Latin1 => "Latin-1",
+        }
+    }
+
+    // Latin-1 has no encoding_rs counterpart (its label maps to Windows-1252), so it is decoded separately
+    fn encoding(&self) -> Option<&'static Encoding> {
+        match self {
+            InputEncoding::Auto | InputEncoding::Latin1 => None,
+            InputEncoding::Utf8 => Some(UTF_8),
+            InputEncoding::Utf16Le => Some(UTF_16LE),
+            InputEncoding::Utf16Be => Some(UTF_16BE),
+            InputEncoding::Windows1252 => Some(WINDOWS_1252),
+            InputEncoding::Windows1251 => Some(WINDOWS_1251),
+            InputEncoding::ShiftJis => Some(SHIFT_JIS),
+        }
+    }
+}
+
+// Guesses the encoding of `sample`, which should be the first bytes of the input.
+pub fn detect_encoding(sample: &[u8]) -> InputEncoding {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return if encoding == UTF_16LE {
+            InputEncoding::Utf16Le
+        } else if encoding == UTF_16BE {
+            InputEncoding::Utf16Be
+        } else {
+            InputEncoding::Utf8
+        };
+    }
+
+    // UTF-16 without a BOM shows up as a NUL in every other byte of ASCII text. NULs are valid
+    // UTF-8, so this is checked first.
+    let even_nuls = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_nuls = sample.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+    let half = sample.len() / 2;
+    if half > 0 && odd_nuls * 3 > half {
+        return InputEncoding::Utf16Le;
+    }
+    if half > 0 && even_nuls * 3 > half {
+        return InputEncoding::Utf16Be;
+    }
+
+    // A sample cut in the middle of a character still counts as UTF-8
+    match std::str::from_utf8(sample) {
+        Ok(_) => return InputEncoding::Utf8,
+        Err(e) if e.error_len().is_none() => return InputEncoding::Utf8,
+        Err(_) => {}
+    }
+
+    if looks_like_shift_jis(sample) {
+        return InputEncoding::ShiftJis;
+    }
+
+    // Cyrillic words are made entirely of high bytes, while accented Latin text
+    // has them scattered between ASCII letters
+    let high_bytes = sample.iter().filter(|&&b| b >= 0x80).count();
+    let high_runs = sample.windows(2).filter(|w| w[0] >= 0xC0 && w[1] >= 0xC0).count();
+    if high_bytes > 0 && high_runs * 2 > high_bytes {
+        return InputEncoding::Windows1251;
+    }
+
+    // These bytes are unassigned in Windows-1252, so they only appear as Latin-1 control codes
+    if sample.iter().any(|b| matches!(b, 0x81 | 0x8D | 0x8F | 0x90 | 0x9D)) {
+        return InputEncoding::Latin1;
+    }
+
+    InputEncoding::Windows1252
+}
+
+fn looks_like_shift_jis(sample: &[u8]) -> bool {
+    // Drop a trailing lead byte that may have been cut off by the sample boundary
+    let sample = match sample.last() {
+        Some(&b) if (0x81..=0x9F).contains(&b) || (0xE0..=0xFC).contains(&b) => &sample[..sample.len() - 1],
+        _ => sample,
+    };
+    let decoded = match SHIFT_JIS.decode_without_bom_handling_and_without_replacement(sample) {
+        Some(decoded) => decoded,
+        None => return false,
+    };
+    let non_ascii = decoded.chars().filter(|c| !c.is_ascii()).count();
+    let japanese = decoded
+        .chars()
+        .filter(|c| matches!(*c as u32, 0x3000..=0x30FF | 0x4E00..=0x9FFF | 0xFF00..=0xFFEF))
+        .count();
+    non_ascii > 0 && japanese * 10 >= non_ascii * 8
+}
+
+// Wraps `reader` so it yields UTF-8, sniffing the encoding first when `choice` is `Auto`.
+// Returns the encoding that was actually used.
+pub fn decode_reader<'a, R: Read + 'a>(mut reader: R, choice: InputEncoding) -> io::Result<(Box<dyn Read + 'a>, InputEncoding)> {
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    (&mut reader).take(SAMPLE_SIZE as u64).read_to_end(&mut sample)?;
+
+    let used = if choice == InputEncoding::Auto { detect_encoding(&sample) } else { choice };
+    let input = Cursor::new(sample).chain(reader);
+
+    let decoded: Box<dyn Read + 'a> = match used.encoding() {
+        Some(encoding) => Box::new(
+            DecodeReaderBytesBuilder::new()
+                .encoding(Some(encoding))
+                .bom_override(true)
+                .build(input),
+        ),
+        None => Box::new(Latin1Reader::new(input)),
+    };
+    Ok((decoded, used))
+}
+
+// Opens `path` and decodes it to UTF-8. See decode_reader.
+pub fn open_file(path: &Path, choice: InputEncoding) -> io::Result<(Box<dyn Read>, InputEncoding)> {
+    decode_reader(File::open(path)?, choice)
+}
+
+// Decodes ISO-8859-1, where every byte maps to the code point of the same value.
+struct Latin1Reader<R> {
+    inner: R,
+    pending: Vec<u8>,
+}
+
+impl<R: Read> Latin1Reader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, pending: Vec::new() }
+    }
+}
+
+impl<R: Read> Read for Latin1Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            let mut raw = vec![0u8; buf.len().clamp(1, 8192)];
+            let n = self.inner.read(&mut raw)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let text: String = raw[..n].iter().map(|&b| b as char).collect();
+            self.pending = text.into_bytes();
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+pub fn encoding_selector(ui: &mut egui::Ui, id_source: &str, encoding: &mut InputEncoding) {
+    ui.horizontal(|ui| {
+        ui.label("Encoding:");
+        egui::ComboBox::from_id_source(id_source)
+            .selected_text(encoding.label())
+            .show_ui(ui, |ui| {
+                for option in InputEncoding::ALL {
+                    ui.selectable_value(encoding, option, option.label());
+                }
+            });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn utf16be(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    #[test]
+    fn ascii_is_utf8() {
+        assert_eq!(detect_encoding(b"name,email\nJon,jon@example.com\n"), InputEncoding::Utf8);
+        assert_eq!(detect_encoding(b""), InputEncoding::Utf8);
+    }
+
+    #[test]
+    fn utf8_cut_in_the_middle_of_a_character_is_utf8() {
+        let text = "name\nJosé".as_bytes();
+        assert_eq!(detect_encoding(&text[..text.len() - 1]), InputEncoding::Utf8);
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        assert_eq!(detect_encoding(b"\xEF\xBB\xBFname\n"), InputEncoding::Utf8);
+        assert_eq!(detect_encoding(&[b"\xFF\xFE".to_vec(), utf16le("name\n")].concat()), InputEncoding::Utf16Le);
+        assert_eq!(detect_encoding(&[b"\xFE\xFF".to_vec(), utf16be("name\n")].concat()), InputEncoding::Utf16Be);
+    }
+
+    #[test]
+    fn utf16_without_a_byte_order_mark() {
+        assert_eq!(detect_encoding(&utf16le("name,email\nJon,jon@example.com\n")), InputEncoding::Utf16Le);
+        assert_eq!(detect_encoding(&utf16be("name,email\nJon,jon@example.com\n")), InputEncoding::Utf16Be);
+    }
+
+    #[test]
+    fn shift_jis() {
+        let (bytes, _, _) = SHIFT_JIS.encode("名前,メール\n山田太郎,taro@example.jp\n");
+        assert_eq!(detect_encoding(&bytes), InputEncoding::ShiftJis);
+    }
+
+    #[test]
+    fn cyrillic_is_windows_1251() {
+        let (bytes, _, _) = WINDOWS_1251.encode("Имя,Город\nИван,Москва\n");
+        assert_eq!(detect_encoding(&bytes), InputEncoding::Windows1251);
+    }
+
+    #[test]
+    fn accented_latin_is_windows_1252() {
+        let (bytes, _, _) = WINDOWS_1252.encode("name,city\nRené,Besançon\nZoë,Orléans\n");
+        assert_eq!(detect_encoding(&bytes), InputEncoding::Windows1252);
+    }
+
+    #[test]
+    fn bytes_unassigned_in_windows_1252_are_latin1() {
+        assert_eq!(detect_encoding(b"name\nRen\xE9,\x81\n"), InputEncoding::Latin1);
+    }
+}
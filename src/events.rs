@@ -0,0 +1,292 @@
+use eframe::egui;
+use egui::{Color32, RichText};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use chrono::Local;
+use crate::history::JobDetails;
+
+// The tab a job belongs to, so its events reach the right status line
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TabId {
+    CsvProcessing,
+    PhoneExtraction,
+    EmailSearch,
+    EmailComparison,
+    Dedupe,
+    Pipeline,
+}
+
+impl TabId {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TabId::CsvProcessing => "CSV Processing",
+            TabId::PhoneExtraction => "Phone Extraction",
+            TabId::EmailSearch => "Email Search",
+            TabId::EmailComparison => "Email Comparison",
+            TabId::Dedupe => "Dedupe",
+            TabId::Pipeline => "Pipeline",
+        }
+    }
+}
+
+// What a finished job reports
+pub struct Summary {
+    pub message: String,
+    pub files: usize,
+    pub errors: usize,
+    pub elapsed: Duration,
+    // Kept in the job history
+    pub details: JobDetails,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+pub enum EventKind {
+    Started(String),
+    Progress(String),
+    FileDone { file: String, detail: String },
+    // `count` matches found since the last such event, the latest of them described
+    Match { count: usize, email: String, file: String, record: usize },
+    Warning(String),
+    Error(String),
+    Finished(Box<Summary>),
+}
+
+impl EventKind {
+    pub fn level(&self) -> Level {
+        match self {
+            EventKind::Warning(_) => Level::Warn,
+            EventKind::Error(_) => Level::Error,
+            _ => Level::Info,
+        }
+    }
+
+    // One-line description for logs
+    pub fn message(&self) -> String {
+        match self {
+            EventKind::Started(message) | EventKind::Progress(message) | EventKind::Warning(message) | EventKind::Error(message) => message.clone(),
+            EventKind::FileDone { file, detail } if detail.is_empty() => format!("Done: {}", file),
+            EventKind::FileDone { file, detail } => format!("Done: {} ({})", file, detail),
+            EventKind::Match { count: 1, email, file, record } => format!("Match: {} at {}:{}", email, file, record),
+            EventKind::Match { count, email, file, record } => format!("{} matches, the latest: {} at {}:{}", count, email, file, record),
+            EventKind::Finished(summary) => {
+                format!("{} ({} files, {} errors, {:.1}s)", summary.message, summary.files, summary.errors, summary.elapsed.as_secs_f64())
+            }
+        }
+    }
+}
+
+pub struct Event {
+    pub tab: TabId,
+    // Shared by every event of one run of a job, so its messages can be picked out of a log
+    pub operation: String,
+    // The input file the event is about, when there is one
+    pub file: Option<String>,
+    pub kind: EventKind,
+}
+
+static NEXT_OPERATION: AtomicU64 = AtomicU64::new(1);
+// Matches found within this long of the last reported one are reported together
+const MATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+// Matches not reported yet, shared by the clones of a sender
+#[derive(Default)]
+struct PendingMatches {
+    count: usize,
+    latest: Option<(String, String, usize)>,
+    sent: Option<Instant>,
+}
+
+// Sends events on behalf of one run of a tab's job. A closed channel only means the app is
+// shutting down, so send failures are ignored.
+#[derive(Clone)]
+pub struct EventSender {
+    tab: TabId,
+    operation: String,
+    tx: Sender<Event>,
+    matches: Arc<Mutex<PendingMatches>>,
+}
+
+impl EventSender {
+    pub fn new(tab: TabId, tx: &Sender<Event>) -> Self {
+        let number = NEXT_OPERATION.fetch_add(1, Ordering::Relaxed);
+        let operation = format!("{}-{}", Local::now().format("%Y%m%d%H%M%S"), number);
+        Self { tab, operation, tx: tx.clone(), matches: Arc::default() }
+    }
+
+    pub fn send(&self, kind: EventKind) {
+        self.send_for(None, kind);
+    }
+
+    fn send_for(&self, file: Option<String>, kind: EventKind) {
+        let _ = self.tx.send(Event { tab: self.tab, operation: self.operation.clone(), file, kind });
+    }
+
+    pub fn started(&self, message: impl Into<String>) {
+        self.send(EventKind::Started(message.into()));
+    }
+
+    pub fn progress(&self, message: impl Into<String>) {
+        self.send(EventKind::Progress(message.into()));
+    }
+
+    pub fn file_done(&self, file: impl Into<String>, detail: impl Into<String>) {
+        let file = file.into();
+        self.send_for(Some(file.clone()), EventKind::FileDone { file, detail: detail.into() });
+    }
+
+    // A search can find thousands of matches a second, so they are sent in batches
+    pub fn found(&self, email: impl Into<String>, file: &str, record: usize) {
+        let mut pending = self.matches.lock().unwrap();
+        pending.count += 1;
+        pending.latest = Some((email.into(), file.to_string(), record));
+        let due = match pending.sent {
+            Some(sent) => sent.elapsed() >= MATCH_INTERVAL,
+            None => true,
+        };
+        if due {
+            self.send_matches(&mut pending);
+        }
+    }
+
+    fn send_matches(&self, pending: &mut PendingMatches) {
+        if let Some((email, file, record)) = pending.latest.take() {
+            self.send_for(Some(file.clone()), EventKind::Match { count: pending.count, email, file, record });
+            pending.count = 0;
+            pending.sent = Some(Instant::now());
+        }
+    }
+
+    pub fn warning(&self, message: impl Into<String>) {
+        self.send(EventKind::Warning(message.into()));
+    }
+
+    pub fn error(&self, message: impl Into<String>) {
+        self.send(EventKind::Error(message.into()));
+    }
+
+    pub fn file_error(&self, file: &str, message: impl Into<String>) {
+        self.send_for(Some(file.to_string()), EventKind::Error(message.into()));
+    }
+
+    pub fn finished(&self, summary: Summary) {
+        self.send_matches(&mut self.matches.lock().unwrap());
+        self.send(EventKind::Finished(Box::new(summary)));
+    }
+}
+
+// A tab's status line, plus the errors and warnings of its jobs, which stay until dismissed
+#[derive(Default)]
+pub struct TabStatus {
+    status: String,
+    matches: usize,
+    problems: Vec<Problem>,
+}
+
+struct Problem {
+    time: String,
+    message: String,
+    is_error: bool,
+}
+
+impl TabStatus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // For messages that come from the UI itself rather than a job
+    pub fn set(&mut self, message: impl Into<String>) {
+        self.status = message.into();
+    }
+
+    pub fn error(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        self.status = message.clone();
+        self.push_problem(message, true);
+    }
+
+    fn push_problem(&mut self, message: String, is_error: bool) {
+        let time = Local::now().format("%H:%M:%S").to_string();
+        self.problems.push(Problem { time, message, is_error });
+    }
+
+    pub fn apply(&mut self, event: EventKind) {
+        match event {
+            EventKind::Started(message) => {
+                self.matches = 0;
+                self.status = message;
+            }
+            EventKind::Match { count, email, file, record } => {
+                self.matches += count;
+                self.status = format!("Match {}: {} at {}:{}", self.matches, email, file, record);
+            }
+            EventKind::Warning(message) => self.push_problem(message, false),
+            EventKind::Error(message) => self.error(message),
+            EventKind::Finished(summary) => {
+                let mut status = format!("{} ({} files", summary.message, summary.files);
+                if summary.errors > 0 {
+                    status.push_str(&format!(", {} errors", summary.errors));
+                }
+                status.push_str(&format!(", {:.1}s)", summary.elapsed.as_secs_f64()));
+                self.status = status;
+            }
+            other => self.status = other.message(),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if !self.status.is_empty() {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("Status:").strong());
+                ui.label(&self.status);
+            });
+        }
+        if self.problems.is_empty() {
+            return;
+        }
+
+        let mut dismissed = None;
+        let mut dismiss_all = false;
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.horizontal(|ui| {
+                let errors = self.problems.iter().filter(|p| p.is_error).count();
+                let warnings = self.problems.len() - errors;
+                ui.label(RichText::new(format!("{} errors, {} warnings", errors, warnings)).strong());
+                dismiss_all = ui.small_button("Dismiss all").clicked();
+            });
+            egui::ScrollArea::vertical().id_source("tab_status_problems").max_height(120.0).show(ui, |ui| {
+                for (index, problem) in self.problems.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.small_button("✖").on_hover_text("Dismiss").clicked() {
+                            dismissed = Some(index);
+                        }
+                        let color = if problem.is_error { Color32::RED } else { Color32::from_rgb(200, 140, 0) };
+                        ui.label(RichText::new(format!("[{}] {}", problem.time, problem.message)).color(color));
+                    });
+                }
+            });
+        });
+        if dismiss_all {
+            self.problems.clear();
+        } else if let Some(index) = dismissed {
+            self.problems.remove(index);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use eframe::egui;
+use egui::{Color32, RichText};
+use rfd::FileDialog;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use chrono::Local;
+use crate::events::{Event, EventKind};
+
+const LOG_FILE_NAME: &str = "csv_processor.log";
+// The log is rotated once it would grow past this size
+const MAX_LOG_BYTES: u64 = 10 * 1024 * 1024;
+// Rotated logs kept besides the current one: `csv_processor.1.log` (newest) to `csv_processor.5.log`
+const KEPT_LOG_FILES: usize = 5;
+
+// In the app's data folder, like the job history, so runs started from any folder log together
+pub fn default_log_dir() -> PathBuf {
+    crate::app_data_dir().join("logs")
+}
+
+// Writes every event as a line of JSON, so a run can be reconstructed after the fact. Matches are
+// only counted, and logged as one line when their job finishes.
+pub struct FileLogger {
+    dir: PathBuf,
+    file: Option<BufWriter<File>>,
+    size: u64,
+    // Operation -> matches found so far
+    matches: HashMap<String, usize>,
+    last_error: Option<String>,
+}
+
+impl FileLogger {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, file: None, size: 0, matches: HashMap::new(), last_error: None }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn set_dir(&mut self, dir: PathBuf) {
+        let flushed = self.close();
+        self.dir = dir;
+        // Reopened in the new folder on the next write
+        self.last_error = flushed.err().map(|e| format!("Could not write to the log file: {}", e));
+    }
+
+    // Logging must never stop a job, so a failure is only remembered for display
+    pub fn record(&mut self, event: &Event) {
+        if let EventKind::Match { count, .. } = event.kind {
+            *self.matches.entry(event.operation.clone()).or_default() += count;
+            return;
+        }
+        let result = self.record_event(event);
+        self.last_error = result.err().map(|e| format!("Could not write to the log file: {}", e));
+    }
+
+    fn record_event(&mut self, event: &Event) -> io::Result<()> {
+        if let EventKind::Finished(_) = event.kind {
+            if let Some(count) = self.matches.remove(&event.operation) {
+                self.write_line(&log_line(event, "INFO", &format!("{} matches", count), None))?;
+            }
+        }
+        self.write_line(&log_line(event, event.kind.level().label(), &event.kind.message(), event.file.as_deref()))?;
+        // Written out once a job is over, or something went wrong, rather than line by line
+        match (&event.kind, &mut self.file) {
+            (EventKind::Finished(_) | EventKind::Error(_), Some(file)) => file.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    fn log_path(&self, index: usize) -> PathBuf {
+        if index == 0 {
+            self.dir.join(LOG_FILE_NAME)
+        } else {
+            self.dir.join(format!("csv_processor.{}.log", index))
+        }
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let line_size = line.len() as u64 + 1;
+        if self.file.is_some() && self.size > 0 && self.size + line_size > MAX_LOG_BYTES {
+            self.rotate()?;
+        }
+        if self.file.is_none() {
+            self.open()?;
+            // The log may already be full from an earlier run
+            if self.size > 0 && self.size + line_size > MAX_LOG_BYTES {
+                self.rotate()?;
+            }
+        }
+        if let Some(file) = &mut self.file {
+            writeln!(file, "{}", line)?;
+            self.size += line_size;
+        }
+        Ok(())
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let file = OpenOptions::new().create(true).append(true).open(self.log_path(0))?;
+        self.size = file.metadata()?.len();
+        self.file = Some(BufWriter::new(file));
+        Ok(())
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        match self.file.take() {
+            Some(mut file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+
+    // csv_processor.log -> csv_processor.1.log -> ... -> csv_processor.5.log, which is dropped
+    fn rotate(&mut self) -> io::Result<()> {
+        self.close()?;
+        let oldest = self.log_path(KEPT_LOG_FILES);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for index in (0..KEPT_LOG_FILES).rev() {
+            let path = self.log_path(index);
+            if path.exists() {
+                fs::rename(&path, self.log_path(index + 1))?;
+            }
+        }
+        self.open()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(format!("Log folder: {}", self.dir.display()));
+            if ui.small_button("Change").clicked() {
+                if let Some(dir) = FileDialog::new().set_directory(&self.dir).pick_folder() {
+                    self.set_dir(dir);
+                }
+            }
+        });
+        if let Some(error) = &self.last_error {
+            ui.label(RichText::new(error).color(Color32::RED));
+        }
+    }
+}
+
+fn log_line(event: &Event, level: &str, message: &str, file: Option<&str>) -> String {
+    json!({
+        "timestamp": Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+        "tab": event.tab.label(),
+        "operation": event.operation,
+        "level": level,
+        "message": message,
+        "file": file,
+    })
+    .to_string()
+}
@@ -0,0 +1,276 @@
+use regex::Regex;
+use serde_json::Value;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use crate::dialect::{self, DialectOptions};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FileFormat {
+    Csv,
+    Tsv,
+    Text,
+    Json,
+    Ndjson,
+    Mbox,
+    Eml,
+}
+
+impl FileFormat {
+    pub fn from_name(name: &str) -> Option<FileFormat> {
+        let ext = Path::new(name).extension()?.to_string_lossy().to_lowercase();
+        match ext.as_str() {
+            "csv" => Some(FileFormat::Csv),
+            "tsv" | "tab" => Some(FileFormat::Tsv),
+            "txt" | "log" => Some(FileFormat::Text),
+            "json" => Some(FileFormat::Json),
+            "ndjson" | "jsonl" => Some(FileFormat::Ndjson),
+            "mbox" | "mbx" => Some(FileFormat::Mbox),
+            "eml" => Some(FileFormat::Eml),
+            _ => None,
+        }
+    }
+
+    // Whether fields come from columns, so a column selection applies
+    pub fn is_delimited(&self) -> bool {
+        matches!(self, FileFormat::Csv | FileFormat::Tsv)
+    }
+}
+
+// One searchable unit: a CSV row, a text line, a JSON object or a mail message
+pub struct Record {
+    pub number: usize,
+    pub fields: Vec<String>,
+    source: RecordSource,
+}
+
+// Kept unformatted so only records that are reported pay for building their text
+enum RecordSource {
+    Text(String),
+    Delimited(csv::StringRecord, u8),
+    Json(Value),
+}
+
+impl Record {
+    // The record as it appeared in the file
+    pub fn text(&self) -> String {
+        match &self.source {
+            RecordSource::Text(text) => text.clone(),
+            RecordSource::Delimited(record, delimiter) => record_text(record, *delimiter),
+            RecordSource::Json(value) => value.to_string(),
+        }
+    }
+}
+
+pub fn email_regex() -> Regex {
+    Regex::new(r"[A-Za-z0-9._%+\-]+@[A-Za-z0-9.\-]+\.[A-Za-z]{2,}").unwrap()
+}
+
+// The dialect options for a delimited `format`: a .tsv file is tab-separated unless chosen otherwise
+pub fn delimited_options(format: FileFormat, options: &DialectOptions) -> DialectOptions {
+    let mut options = *options;
+    if format == FileFormat::Tsv && options.delimiter.is_none() {
+        options.delimiter = Some('\t');
+    }
+    options
+}
+
+// Reads `reader` as `format` and calls `visit` for each record until it returns false.
+// `json_fields` are dotted paths (e.g. `contact.email`); when empty every string value is used.
+// The dialect of delimited files is detected unless fixed by `dialect_options`. A header row is
+// read as a record, so record numbers stay line-based. NDJSON lines that are not valid JSON are
+// passed to `skipped` with their line number and the reason, and reading goes on.
+pub fn read_records(
+    format: FileFormat,
+    reader: &mut dyn Read,
+    json_fields: &[String],
+    dialect_options: &DialectOptions,
+    visit: &mut dyn FnMut(Record) -> bool,
+    skipped: &mut dyn FnMut(usize, String),
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        FileFormat::Csv | FileFormat::Tsv => {
+            let (input, dialect) = dialect::detect(Box::new(reader), &delimited_options(format, dialect_options))?;
+            let mut builder = dialect.reader_builder();
+            builder.has_headers(false).flexible(true);
+            for (index, result) in builder.from_reader(input).records().enumerate() {
+                let record = result?;
+                let fields = record.iter().map(|field| field.trim().to_string()).collect();
+                if !visit(Record { number: index + 1, fields, source: RecordSource::Delimited(record, dialect.delimiter) }) {
+                    break;
+                }
+            }
+        }
+        FileFormat::Text => {
+            for (index, line) in BufReader::new(reader).lines().enumerate() {
+                let line = line?;
+                let fields = line
+                    .split(|c: char| c.is_whitespace() || ",;<>\"'()[]".contains(c))
+                    .filter(|token| !token.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                if !visit(Record { number: index + 1, fields, source: RecordSource::Text(line) }) {
+                    break;
+                }
+            }
+        }
+        FileFormat::Json => {
+            let mut text = String::new();
+            reader.read_to_string(&mut text)?;
+            match serde_json::from_str::<Value>(&text) {
+                Ok(Value::Array(items)) => {
+                    for (index, item) in items.into_iter().enumerate() {
+                        if !visit(Record { number: index + 1, fields: json_values(&item, json_fields), source: RecordSource::Json(item) }) {
+                            break;
+                        }
+                    }
+                }
+                Ok(value) => {
+                    visit(Record { number: 1, fields: json_values(&value, json_fields), source: RecordSource::Json(value) });
+                }
+                // Files named .json are often newline-delimited exports
+                Err(_) => read_ndjson(&mut text.as_bytes(), json_fields, visit, skipped)?,
+            }
+        }
+        FileFormat::Ndjson => read_ndjson(reader, json_fields, visit, skipped)?,
+        FileFormat::Mbox => {
+            let email_regex = email_regex();
+            let mut number = 0;
+            let mut message = Vec::new();
+            for line in BufReader::new(reader).lines() {
+                let line = line?;
+                if line.starts_with("From ") {
+                    if !message.is_empty() {
+                        number += 1;
+                        if !visit(mail_record(number, &message, &email_regex)) {
+                            return Ok(());
+                        }
+                    }
+                    message.clear();
+                } else {
+                    message.push(line);
+                }
+            }
+            if !message.is_empty() {
+                visit(mail_record(number + 1, &message, &email_regex));
+            }
+        }
+        FileFormat::Eml => {
+            let lines: Vec<String> = BufReader::new(reader).lines().collect::<Result<_, _>>()?;
+            visit(mail_record(1, &lines, &email_regex()));
+        }
+    }
+    Ok(())
+}
+
+fn read_ndjson(
+    reader: &mut dyn Read,
+    json_fields: &[String],
+    visit: &mut dyn FnMut(Record) -> bool,
+    skipped: &mut dyn FnMut(usize, String),
+) -> Result<(), Box<dyn Error>> {
+    for (index, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: Value = match serde_json::from_str(&line) {
+            Ok(value) => value,
+            Err(e) => {
+                skipped(index + 1, e.to_string());
+                continue;
+            }
+        };
+        if !visit(Record { number: index + 1, fields: json_values(&value, json_fields), source: RecordSource::Text(line) }) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn json_values(value: &Value, json_fields: &[String]) -> Vec<String> {
+    let mut values = Vec::new();
+    if json_fields.is_empty() {
+        collect_strings(value, &mut values);
+    } else {
+        for path in json_fields {
+            let mut current = vec![value];
+            for key in path.split('.') {
+                current = current
+                    .into_iter()
+                    .flat_map(|v| match v {
+                        // Arrays along the path are searched element by element
+                        Value::Array(items) => items.iter().filter_map(|item| item.get(key)).collect::<Vec<_>>(),
+                        _ => v.get(key).into_iter().collect(),
+                    })
+                    .collect();
+            }
+            for v in current {
+                collect_strings(v, &mut values);
+            }
+        }
+    }
+    values
+}
+
+fn collect_strings(value: &Value, values: &mut Vec<String>) {
+    match value {
+        Value::String(s) => values.push(s.trim().to_string()),
+        Value::Array(items) => items.iter().for_each(|item| collect_strings(item, values)),
+        Value::Object(map) => map.values().for_each(|item| collect_strings(item, values)),
+        _ => {}
+    }
+}
+
+// Re-encodes a parsed row so quoting is preserved in reports
+pub fn record_text(record: &csv::StringRecord, delimiter: u8) -> String {
+    let mut writer = csv::WriterBuilder::new().delimiter(delimiter).from_writer(Vec::new());
+    if writer.write_record(record).is_err() {
+        return record.iter().collect::<Vec<_>>().join(&(delimiter as char).to_string());
+    }
+    let bytes = writer.into_inner().unwrap_or_default();
+    // Only the line terminator goes; trailing empty fields and spaces are part of the row
+    let text = String::from_utf8_lossy(&bytes);
+    text.strip_suffix('\n').map(|line| line.strip_suffix('\r').unwrap_or(line)).unwrap_or(&text).to_string()
+}
+
+// A message is reported by its headers, and searched by the addresses in them
+fn mail_record(number: usize, lines: &[String], email_regex: &Regex) -> Record {
+    let headers = unfold_headers(lines);
+    Record {
+        number,
+        fields: header_addresses(&headers, email_regex),
+        source: RecordSource::Text(headers.join("\n")),
+    }
+}
+
+fn unfold_headers(lines: &[String]) -> Vec<String> {
+    // Unfold continuation lines so each header is a single string
+    let mut headers: Vec<String> = Vec::new();
+    for line in lines {
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(last) = headers.last_mut() {
+                last.push(' ');
+                last.push_str(line.trim());
+            }
+        } else {
+            headers.push(line.clone());
+        }
+    }
+    headers
+}
+
+// Addresses from the sender and recipient headers of an RFC 822 message
+fn header_addresses(headers: &[String], email_regex: &Regex) -> Vec<String> {
+    const ADDRESS_HEADERS: [&str; 6] = ["from", "to", "cc", "bcc", "reply-to", "sender"];
+
+    headers
+        .iter()
+        .filter_map(|header| header.split_once(':'))
+        .filter(|(name, _)| ADDRESS_HEADERS.contains(&name.trim().to_lowercase().as_str()))
+        .flat_map(|(_, value)| email_regex.find_iter(value).map(|m| m.as_str().to_string()).collect::<Vec<_>>())
+        .collect()
+}
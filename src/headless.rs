@@ -0,0 +1,46 @@
+use std::path::Path;
+use std::sync::mpsc::channel;
+use std::thread;
+use crate::events::{Event, Level};
+use crate::file_log::{self, FileLogger};
+use crate::history::{self, HistoryStore, JobTracker};
+use crate::pipeline::{self, Pipeline};
+
+// Runs the steps of a pipeline file in order without opening a window, printing every message and
+// logging and recording the jobs as the app does. Stops at a step that cannot be started.
+// Returns how many errors were reported.
+pub fn run_pipeline(path: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    let pipeline = Pipeline::load(path)?;
+    let (tx, rx) = channel::<Event>();
+    let reporter = thread::spawn(move || {
+        let mut file_logger = FileLogger::new(file_log::default_log_dir());
+        let store = HistoryStore::new(history::default_history_path());
+        let mut tracker = JobTracker::default();
+        let mut errors = 0;
+        for event in rx {
+            let level = event.kind.level();
+            if level == Level::Error {
+                errors += 1;
+            }
+            println!("[{}] [{}] {}", level.label(), event.tab.label(), event.kind.message());
+            file_logger.record(&event);
+            if let Some(job) = tracker.record(&event) {
+                if let Err(e) = store.append(&job) {
+                    eprintln!("Could not save the job to the history: {}", e);
+                }
+            }
+        }
+        errors
+    });
+
+    let mut step_errors = 0;
+    for (index, step) in pipeline.steps.iter().enumerate() {
+        if let Err(message) = pipeline::run_step(step, &tx) {
+            eprintln!("Step {} ({}): {}", index + 1, step.operation.tab().label(), message);
+            step_errors += 1;
+            break;
+        }
+    }
+    drop(tx);
+    Ok(reporter.join().unwrap_or(0) + step_errors)
+}
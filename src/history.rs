@@ -0,0 +1,342 @@
+use eframe::egui;
+use egui::{Color32, RichText};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Duration as ChronoDuration, Local};
+use crate::dialect::DialectOptions;
+use crate::dedupe::DedupeSettings;
+use crate::email_search::EmailSearchSettings;
+use crate::encoding::InputEncoding;
+use crate::events::{Event, EventKind, TabId};
+use crate::pipeline_tab::ChainSettings;
+
+// One JSON object per line, appended as jobs finish
+pub const HISTORY_FILE: &str = "job_history.jsonl";
+
+// What a job was run with, complete enough to run it again
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "tab")]
+pub enum JobSettings {
+    CsvProcessing {
+        states: Vec<String>,
+        email_domains: Vec<String>,
+        encoding: InputEncoding,
+        #[serde(default)]
+        dialect: DialectOptions,
+        #[serde(default)]
+        lenient: bool,
+        #[serde(default)]
+        output_dir: PathBuf,
+    },
+    PhoneExtraction {
+        encoding: InputEncoding,
+        #[serde(default)]
+        dialect: DialectOptions,
+        #[serde(default)]
+        lenient: bool,
+        #[serde(default = "default_phone_output")]
+        output_file: PathBuf,
+    },
+    EmailComparison { first: PathBuf, second: PathBuf, output: PathBuf, encoding: InputEncoding },
+    EmailSearch { folder: PathBuf, email_list: Option<PathBuf>, results_file: PathBuf, settings: Box<EmailSearchSettings> },
+    Pipeline { settings: Box<ChainSettings> },
+    Dedupe { settings: Box<DedupeSettings> },
+}
+
+// Where phone numbers went before the output file could be chosen
+pub fn default_phone_output() -> PathBuf {
+    PathBuf::from("phone_numbers.txt")
+}
+
+impl JobSettings {
+    pub fn tab(&self) -> TabId {
+        match self {
+            JobSettings::CsvProcessing { .. } => TabId::CsvProcessing,
+            JobSettings::PhoneExtraction { .. } => TabId::PhoneExtraction,
+            JobSettings::EmailComparison { .. } => TabId::EmailComparison,
+            JobSettings::EmailSearch { .. } => TabId::EmailSearch,
+            JobSettings::Pipeline { .. } => TabId::Pipeline,
+            JobSettings::Dedupe { .. } => TabId::Dedupe,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InputFile {
+    pub path: PathBuf,
+    // None when the file could not be read at the time
+    pub size: Option<u64>,
+    // Recorded when the file is not hashed, so a change can still be told
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    pub sha256: Option<String>,
+}
+
+impl InputFile {
+    pub fn hash(path: &Path) -> Self {
+        Self { sha256: sha256_file(path).ok(), ..Self::stat(path) }
+    }
+
+    // Size and modification time only, for inputs too large to read a second time
+    pub fn stat(path: &Path) -> Self {
+        let metadata = path.metadata().ok();
+        let modified = metadata.as_ref().and_then(|m| m.modified().ok()).map(|time| DateTime::<Local>::from(time).to_rfc3339());
+        Self { path: path.to_path_buf(), size: metadata.map(|m| m.len()), modified, sha256: None }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OutputFile {
+    pub path: PathBuf,
+    pub rows: u64,
+}
+
+// What a job reports about itself when it finishes
+#[derive(Clone)]
+pub struct JobDetails {
+    pub settings: JobSettings,
+    pub inputs: Vec<InputFile>,
+    pub outputs: Vec<OutputFile>,
+    pub rows: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub operation: String,
+    pub started_at: String,
+    pub duration_secs: f64,
+    pub message: String,
+    pub files: usize,
+    pub rows: u64,
+    pub errors: Vec<String>,
+    pub warnings: usize,
+    pub inputs: Vec<InputFile>,
+    pub outputs: Vec<OutputFile>,
+    pub settings: JobSettings,
+}
+
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub fn default_history_path() -> PathBuf {
+    crate::app_data_dir().join(HISTORY_FILE)
+}
+
+pub struct HistoryStore {
+    path: PathBuf,
+}
+
+impl HistoryStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    // Returns the jobs, and why each line that cannot be read was skipped, e.g. after a crash mid-write
+    pub fn load(&self) -> io::Result<(Vec<JobRecord>, Vec<String>)> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((Vec::new(), Vec::new())),
+            Err(e) => return Err(e),
+        };
+        let mut jobs = Vec::new();
+        let mut skipped = Vec::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let parsed = match line {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => serde_json::from_str(&line).map_err(|e| e.to_string()),
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => Err(e.to_string()),
+                Err(e) => return Err(e),
+            };
+            match parsed {
+                Ok(job) => jobs.push(job),
+                Err(e) => skipped.push(format!("line {}: {}", index + 1, e)),
+            }
+        }
+        Ok((jobs, skipped))
+    }
+
+    pub fn append(&self, job: &JobRecord) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(job)?)
+    }
+}
+
+// Collects the errors and warnings of each running operation until it finishes
+#[derive(Default)]
+pub struct JobTracker {
+    running: HashMap<String, (Vec<String>, usize)>,
+}
+
+impl JobTracker {
+    // Returns the finished job when `event` ends an operation
+    pub fn record(&mut self, event: &Event) -> Option<JobRecord> {
+        match &event.kind {
+            EventKind::Error(message) => self.running.entry(event.operation.clone()).or_default().0.push(message.clone()),
+            EventKind::Warning(_) => self.running.entry(event.operation.clone()).or_default().1 += 1,
+            EventKind::Finished(summary) => {
+                let (errors, warnings) = self.running.remove(&event.operation).unwrap_or_default();
+                let started_at = Local::now() - ChronoDuration::from_std(summary.elapsed).unwrap_or_else(|_| ChronoDuration::zero());
+                let details = summary.details.clone();
+                return Some(JobRecord {
+                    operation: event.operation.clone(),
+                    started_at: started_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+                    duration_secs: summary.elapsed.as_secs_f64(),
+                    message: summary.message.clone(),
+                    files: summary.files,
+                    rows: details.rows,
+                    errors,
+                    warnings,
+                    inputs: details.inputs,
+                    outputs: details.outputs,
+                    settings: details.settings,
+                });
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+// Past jobs of every tab, newest first, with their inputs, outputs and settings
+pub struct HistoryTab {
+    store: HistoryStore,
+    tracker: JobTracker,
+    jobs: Vec<JobRecord>,
+    selected: Option<String>,
+    filter: String,
+    status: Option<String>,
+}
+
+impl HistoryTab {
+    pub fn new(path: PathBuf) -> Self {
+        let mut tab = Self {
+            store: HistoryStore::new(path),
+            tracker: JobTracker::default(),
+            jobs: Vec::new(),
+            selected: None,
+            filter: String::new(),
+            status: None,
+        };
+        tab.reload();
+        tab
+    }
+
+    fn reload(&mut self) {
+        match self.store.load() {
+            Ok((jobs, skipped)) => {
+                self.jobs = jobs;
+                if !skipped.is_empty() {
+                    self.status = Some(format!("Skipped {} unreadable jobs in {}: {}", skipped.len(), self.store.path.display(), skipped.join("; ")));
+                }
+            }
+            Err(e) => self.status = Some(format!("Could not read the job history: {}", e)),
+        }
+    }
+
+    pub fn record(&mut self, event: &Event) {
+        if let Some(job) = self.tracker.record(event) {
+            if let Err(e) = self.store.append(&job) {
+                self.status = Some(format!("Could not save the job to the history: {}", e));
+            }
+            self.jobs.push(job);
+        }
+    }
+
+    fn matches_filter(job: &JobRecord, filter: &str) -> bool {
+        filter.is_empty()
+            || job.started_at.contains(filter)
+            || job.message.to_lowercase().contains(filter)
+            || job.settings.tab().label().to_lowercase().contains(filter)
+            || job.inputs.iter().any(|input| {
+                input.path.to_string_lossy().to_lowercase().contains(filter)
+                    || input.sha256.as_deref().is_some_and(|hash| hash.starts_with(filter))
+            })
+            || job.outputs.iter().any(|output| output.path.to_string_lossy().to_lowercase().contains(filter))
+    }
+
+    // Returns a job the user asked to run again
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<JobRecord> {
+        let mut rerun = None;
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.filter).hint_text("Filter by date, tab, file or hash").desired_width(250.0));
+            if ui.button("⟳ Reload").clicked() {
+                self.status = None;
+                self.reload();
+            }
+            ui.label(format!("{} jobs", self.jobs.len()));
+        });
+        if let Some(status) = &self.status {
+            ui.label(RichText::new(status).color(Color32::RED));
+        }
+
+        let filter = self.filter.to_lowercase();
+        egui::ScrollArea::vertical().id_source("history_jobs").max_height(200.0).auto_shrink([false, true]).show(ui, |ui| {
+            for job in self.jobs.iter().rev().filter(|job| Self::matches_filter(job, &filter)) {
+                let mut text = format!("{}  {}  {}", &job.started_at[..job.started_at.len().min(19)].replace('T', " "), job.settings.tab().label(), job.message);
+                if !job.errors.is_empty() {
+                    text.push_str(&format!(" ({} errors)", job.errors.len()));
+                }
+                let selected = self.selected.as_ref() == Some(&job.operation);
+                if ui.selectable_label(selected, text).clicked() {
+                    self.selected = if selected { None } else { Some(job.operation.clone()) };
+                }
+            }
+        });
+
+        let job = self.selected.as_ref().and_then(|operation| self.jobs.iter().find(|job| &job.operation == operation))?;
+        ui.separator();
+        egui::Grid::new("history_job").num_columns(2).show(ui, |ui| {
+            ui.label(RichText::new("Operation:").strong());
+            ui.label(&job.operation);
+            ui.end_row();
+            ui.label(RichText::new("Started:").strong());
+            ui.label(&job.started_at);
+            ui.end_row();
+            ui.label(RichText::new("Duration:").strong());
+            ui.label(format!("{:.1}s", job.duration_secs));
+            ui.end_row();
+            ui.label(RichText::new("Counts:").strong());
+            ui.label(format!("{} files, {} rows read, {} errors, {} warnings", job.files, job.rows, job.errors.len(), job.warnings));
+            ui.end_row();
+        });
+        ui.collapsing(format!("Inputs ({})", job.inputs.len()), |ui| {
+            for input in &job.inputs {
+                let identity = match (&input.sha256, &input.modified) {
+                    (Some(hash), _) => format!("sha256:{}", hash),
+                    (None, Some(modified)) => format!("{} bytes, modified {}", input.size.unwrap_or(0), modified),
+                    (None, None) => "not readable".to_string(),
+                };
+                ui.label(RichText::new(format!("{}  {}", input.path.display(), identity)).monospace());
+            }
+        });
+        ui.collapsing(format!("Outputs ({})", job.outputs.len()), |ui| {
+            for output in &job.outputs {
+                ui.label(format!("{}: {} rows", output.path.display(), output.rows));
+            }
+        });
+        ui.collapsing("Settings", |ui| {
+            ui.label(RichText::new(serde_json::to_string_pretty(&job.settings).unwrap_or_default()).monospace());
+        });
+        if !job.errors.is_empty() {
+            ui.collapsing(format!("Errors ({})", job.errors.len()), |ui| {
+                for error in &job.errors {
+                    ui.label(RichText::new(error).color(Color32::RED));
+                }
+            });
+        }
+        if ui.button("🔁 Re-run with these settings").clicked() {
+            rerun = Some(job.clone());
+        }
+        rerun
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+// Shared between the UI and worker threads of a long-running job. Workers call
+// `checkpoint` regularly; the UI flips the flags.
+#[derive(Default)]
+pub struct JobControl {
+    cancelled: AtomicBool,
+    paused: AtomicBool,
+    finished: AtomicBool,
+    pause_lock: Mutex<()>,
+    resumed: Condvar,
+}
+
+impl JobControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        // Wake paused workers so they can see the cancellation
+        self.resume();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        let _guard = self.pause_lock.lock().unwrap();
+        self.paused.store(false, Ordering::SeqCst);
+        self.resumed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    // Marks the job finished once the guard is dropped, including when a worker panics
+    pub fn finish_on_drop(self: &Arc<Self>) -> FinishGuard {
+        FinishGuard(self.clone())
+    }
+
+    // Blocks while the job is paused. Returns false once the job has been cancelled.
+    pub fn checkpoint(&self) -> bool {
+        if self.paused.load(Ordering::Relaxed) {
+            let mut guard = self.pause_lock.lock().unwrap();
+            while self.paused.load(Ordering::SeqCst) && !self.is_cancelled() {
+                guard = self.resumed.wait(guard).unwrap();
+            }
+        }
+        !self.is_cancelled()
+    }
+}
+
+pub struct FinishGuard(Arc<JobControl>);
+
+impl Drop for FinishGuard {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+// Caps how many bytes the workers of a job hold in memory at once
+pub struct MemoryBudget {
+    limit: usize,
+    used: Mutex<usize>,
+    released: Condvar,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize) -> Self {
+        Self { limit, used: Mutex::new(0), released: Condvar::new() }
+    }
+
+    // Blocks until `bytes` fit in the budget. A request larger than the whole budget
+    // waits until nothing else is held, so it can still make progress.
+    pub fn reserve(&self, bytes: usize) -> Reservation<'_> {
+        let mut used = self.used.lock().unwrap();
+        while *used > 0 && *used + bytes > self.limit {
+            used = self.released.wait(used).unwrap();
+        }
+        *used += bytes;
+        Reservation { budget: self, bytes }
+    }
+}
+
+// Returns its bytes to the budget when dropped
+pub struct Reservation<'a> {
+    budget: &'a MemoryBudget,
+    bytes: usize,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        *self.budget.used.lock().unwrap() -= self.bytes;
+        self.budget.released.notify_all();
+    }
+}
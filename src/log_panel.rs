@@ -0,0 +1,123 @@
+use eframe::egui;
+use egui::{Color32, RichText};
+use rfd::FileDialog;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use chrono::Local;
+use crate::events::{Event, Level, TabId};
+
+// How many messages are kept before the oldest are dropped
+const LOG_CAPACITY: usize = 5000;
+
+pub struct LogEntry {
+    pub time: String,
+    pub level: Level,
+    pub tab: TabId,
+    pub message: String,
+}
+
+impl LogEntry {
+    fn line(&self) -> String {
+        format!("{} [{}] [{}] {}", self.time, self.level.label(), self.tab.label(), self.message)
+    }
+}
+
+// Messages from every tab's jobs, kept after they have been shown
+pub struct LogPanel {
+    entries: VecDeque<LogEntry>,
+    show_info: bool,
+    show_warn: bool,
+    show_error: bool,
+    search: String,
+    auto_scroll: bool,
+    save_status: Option<String>,
+}
+
+impl LogPanel {
+    pub fn new() -> Self {
+        Self {
+            entries: VecDeque::with_capacity(LOG_CAPACITY),
+            show_info: true,
+            show_warn: true,
+            show_error: true,
+            search: String::new(),
+            auto_scroll: true,
+            save_status: None,
+        }
+    }
+
+    pub fn push(&mut self, tab: TabId, level: Level, message: String) {
+        if self.entries.len() == LOG_CAPACITY {
+            self.entries.pop_front();
+        }
+        let time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        self.entries.push_back(LogEntry { time, level, tab, message });
+    }
+
+    pub fn record(&mut self, event: &Event) {
+        self.push(event.tab, event.kind.level(), event.kind.message());
+    }
+
+    fn is_visible(&self, entry: &LogEntry, search: &str) -> bool {
+        let level_shown = match entry.level {
+            Level::Info => self.show_info,
+            Level::Warn => self.show_warn,
+            Level::Error => self.show_error,
+        };
+        level_shown && (search.is_empty() || entry.message.to_lowercase().contains(search))
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        for entry in &self.entries {
+            writeln!(file, "{}", entry.line())?;
+        }
+        Ok(())
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.show_info, "Info");
+            ui.checkbox(&mut self.show_warn, "Warnings");
+            ui.checkbox(&mut self.show_error, "Errors");
+            ui.add(egui::TextEdit::singleline(&mut self.search).hint_text("Search log").desired_width(150.0));
+            ui.checkbox(&mut self.auto_scroll, "Auto-scroll");
+            if ui.button("💾 Save log").clicked() {
+                let file_name = format!("log_{}.txt", Local::now().format("%Y%m%d_%H%M%S"));
+                if let Some(path) = FileDialog::new().add_filter("Text file", &["txt", "log"]).set_file_name(&file_name).save_file() {
+                    self.save_status = Some(match self.save(&path) {
+                        Ok(()) => format!("Saved {} messages to {}", self.entries.len(), path.display()),
+                        Err(e) => format!("Error saving log: {}", e),
+                    });
+                }
+            }
+            if ui.button("Clear").clicked() {
+                self.entries.clear();
+            }
+        });
+        if let Some(status) = &self.save_status {
+            ui.label(status);
+        }
+
+        let search = self.search.to_lowercase();
+        let visible: Vec<&LogEntry> = self.entries.iter().filter(|entry| self.is_visible(entry, &search)).collect();
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        egui::ScrollArea::vertical()
+            .id_source("log_panel")
+            .max_height(150.0)
+            .auto_shrink([false, true])
+            .stick_to_bottom(self.auto_scroll)
+            .show_rows(ui, row_height, visible.len(), |ui, rows| {
+                for entry in &visible[rows] {
+                    let color = match entry.level {
+                        Level::Info => ui.visuals().text_color(),
+                        Level::Warn => Color32::from_rgb(200, 140, 0),
+                        Level::Error => Color32::RED,
+                    };
+                    ui.label(RichText::new(entry.line()).monospace().color(color));
+                }
+            });
+    }
+}
@@ -1,50 +1,120 @@
-use csv::ReaderBuilder;
-use csv::Writer;
+use csv::StringRecord;
+use csv::{Writer, WriterBuilder};
 use eframe::egui;
-use rfd::FileDialog;
 use std::fs::File;
-use std::path::PathBuf;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Receiver, Sender};
-use std::thread;
-use egui::{Color32, RichText, Stroke, Rounding};
+use egui::{Color32, RichText};
 use regex::Regex;
 use std::io::Write;
+use serde::{Deserialize, Serialize};
 
 mod csv_processing;
 mod phone_extraction;
 mod email_search;
 mod email_comparison;
+mod encoding;
+mod archive;
+mod formats;
+mod walk;
+mod job;
+mod search_state;
+mod results_table;
+mod matching;
+mod chunks;
+mod progress;
+mod events;
+mod log_panel;
+mod file_log;
+mod history;
+mod presets;
+mod pipeline;
+mod headless;
+mod pipeline_tab;
+mod preview;
+mod dialect;
+mod records;
+mod dedupe;
 
-use csv_processing::CsvProcessingTab;
-use phone_extraction::PhoneExtractionTab;
-use email_search::EmailSearchTab;
-use email_comparison::EmailComparisonTab;
+use csv_processing::{CsvProcessingSettings, CsvProcessingTab};
+use phone_extraction::{PhoneExtractionSettings, PhoneExtractionTab};
+use email_search::{EmailSearchPreset, EmailSearchTab};
+use email_comparison::{EmailComparisonSettings, EmailComparisonTab};
+use dedupe::{DedupeSettings, DedupeTab};
+use encoding::InputEncoding;
+use records::CsvReading;
+use progress::Progress;
+use events::{Event, TabId};
+use log_panel::LogPanel;
+use file_log::FileLogger;
+use history::{HistoryTab, JobRecord};
+use pipeline::{Pipeline, PipelineStep};
+use pipeline_tab::{ChainSettings, PipelineTab};
+use rfd::FileDialog;
 
-#[derive(PartialEq)]
+// Also names the folder where eframe, the job history, the presets and the logs are kept
+const APP_NAME: &str = "CSV Processor";
+
+// The app's data folder, where eframe keeps its own state, so it is the same from any working
+// directory. Falls back to the working directory when there is none.
+fn app_data_dir() -> PathBuf {
+    directories_next::ProjectDirs::from("", "", APP_NAME)
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default())
+}
+
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
 enum Theme {
+    #[default]
     Light,
     Dark,
 }
 
+// The last-used settings of every tab, restored on the next launch
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SavedSettings {
+    theme: Theme,
+    log_dir: Option<PathBuf>,
+    csv_processing: CsvProcessingSettings,
+    phone_extraction: PhoneExtractionSettings,
+    email_search: EmailSearchPreset,
+    email_comparison: EmailComparisonSettings,
+    dedupe: DedupeSettings,
+    pipeline: ChainSettings,
+}
+
 #[derive(PartialEq)]
 enum Tab {
     CsvProcessing,
     PhoneExtraction,
     EmailSearch,
     EmailComparison, // Add this line
+    Dedupe,
+    Pipeline,
+    History,
 }
 
 struct CsvProcessorApp {
     selected_files: Vec<PathBuf>,
-    processing_status: String,
-    rx: Receiver<String>,
-    tx: Sender<String>,
+    rx: Receiver<Event>,
+    tx: Sender<Event>,
     theme: Theme,
     current_tab: Tab,
     csv_processing_tab: CsvProcessingTab,
     phone_extraction_tab: PhoneExtractionTab,
     email_search_tab: EmailSearchTab,
     email_comparison_tab: EmailComparisonTab, // Add this line
+    dedupe_tab: DedupeTab,
+    pipeline_tab: PipelineTab,
+    log_panel: LogPanel,
+    file_logger: FileLogger,
+    history_tab: HistoryTab,
+    // The pipeline file last opened or saved, whose steps were loaded into the tabs
+    pipeline_path: Option<PathBuf>,
+    pipeline: Pipeline,
+    pipeline_status: Option<String>,
 }
 
 impl eframe::App for CsvProcessorApp {
@@ -64,17 +134,44 @@ impl eframe::App for CsvProcessorApp {
                 ui.selectable_value(&mut self.current_tab, Tab::PhoneExtraction, "Phone Extraction");
                 ui.selectable_value(&mut self.current_tab, Tab::EmailSearch, "Email Search");
                 ui.selectable_value(&mut self.current_tab, Tab::EmailComparison, "Email Comparison"); // Add this line
+                ui.selectable_value(&mut self.current_tab, Tab::Dedupe, "Dedupe");
+                ui.selectable_value(&mut self.current_tab, Tab::Pipeline, "Pipeline");
+                ui.selectable_value(&mut self.current_tab, Tab::History, "History");
+            });
+            ui.horizontal(|ui| {
+                if ui.button("📂 Open pipeline").clicked() {
+                    self.open_pipeline();
+                }
+                if ui.button("💾 Save pipeline").clicked() {
+                    self.save_pipeline();
+                }
+                if let Some(status) = &self.pipeline_status {
+                    ui.label(status);
+                }
             });
 
             ui.add_space(10.0);
 
             match self.current_tab {
-                Tab::CsvProcessing => self.csv_processing_tab.ui(ui, &mut self.selected_files, &mut self.processing_status, &self.tx),
-                Tab::PhoneExtraction => self.phone_extraction_tab.ui(ui, &mut self.selected_files, &mut self.processing_status, &self.tx),
-                Tab::EmailSearch => self.email_search_tab.ui(ui, &mut self.processing_status, &self.tx),
-                Tab::EmailComparison => self.email_comparison_tab.ui(ui, &mut self.processing_status, &self.tx), // Add this line
+                Tab::CsvProcessing => self.csv_processing_tab.ui(ui, &mut self.selected_files, &self.tx),
+                Tab::PhoneExtraction => self.phone_extraction_tab.ui(ui, &mut self.selected_files, &self.tx),
+                Tab::EmailSearch => self.email_search_tab.ui(ui, &self.tx),
+                Tab::EmailComparison => self.email_comparison_tab.ui(ui, &self.tx), // Add this line
+                Tab::Dedupe => self.dedupe_tab.ui(ui, &mut self.selected_files, &self.tx),
+                Tab::Pipeline => self.pipeline_tab.ui(ui, &self.tx),
+                Tab::History => {
+                    if let Some(job) = self.history_tab.ui(ui) {
+                        self.rerun(&job);
+                    }
+                }
             }
 
+            ui.separator();
+            egui::CollapsingHeader::new("Log").default_open(true).show(ui, |ui| {
+                self.file_logger.ui(ui);
+                self.log_panel.ui(ui);
+            });
+
             ui.with_layout(egui::Layout::bottom_up(egui::Align::RIGHT), |ui| {
                 ui.horizontal(|ui| {
                     ui.label("Theme:");
@@ -85,106 +182,338 @@ impl eframe::App for CsvProcessorApp {
         });
 
         // Check for new messages from the processing thread
-        while let Ok(message) = self.rx.try_recv() {
-            self.processing_status = message;
+        while let Ok(event) = self.rx.try_recv() {
+            self.file_logger.record(&event);
+            self.log_panel.record(&event);
+            self.history_tab.record(&event);
+            match event.tab {
+                TabId::CsvProcessing => self.csv_processing_tab.handle_event(event.kind),
+                TabId::PhoneExtraction => self.phone_extraction_tab.handle_event(event.kind),
+                TabId::EmailSearch => self.email_search_tab.handle_event(event.kind),
+                TabId::EmailComparison => self.email_comparison_tab.handle_event(event.kind),
+                TabId::Dedupe => self.dedupe_tab.handle_event(event.kind),
+                TabId::Pipeline => self.pipeline_tab.handle_event(event.kind),
+            }
         }
     }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        let saved = SavedSettings {
+            theme: self.theme,
+            // Only a chosen folder is kept, so the default follows the app data folder
+            log_dir: Some(self.file_logger.dir().to_path_buf()).filter(|dir| *dir != file_log::default_log_dir()),
+            csv_processing: self.csv_processing_tab.settings().clone(),
+            phone_extraction: self.phone_extraction_tab.settings().clone(),
+            email_search: self.email_search_tab.preset(),
+            email_comparison: self.email_comparison_tab.settings().clone(),
+            dedupe: self.dedupe_tab.settings().clone(),
+            pipeline: self.pipeline_tab.settings().clone(),
+        };
+        eframe::set_value(storage, eframe::APP_KEY, &saved);
+    }
 }
 
 impl CsvProcessorApp {
+    // The tab's settings as a pipeline operation. The Pipeline tab has no operation of its own.
+    fn pipeline_operation(&self, tab: TabId) -> Option<pipeline::Operation> {
+        match tab {
+            TabId::CsvProcessing => Some(self.csv_processing_tab.pipeline_step(&self.selected_files)),
+            TabId::PhoneExtraction => Some(self.phone_extraction_tab.pipeline_step(&self.selected_files)),
+            TabId::EmailSearch => Some(self.email_search_tab.pipeline_step()),
+            TabId::EmailComparison => Some(self.email_comparison_tab.pipeline_step()),
+            TabId::Dedupe => Some(self.dedupe_tab.pipeline_step(&self.selected_files)),
+            TabId::Pipeline => None,
+        }
+    }
+
+    // Loads the first step of each operation into its tab; the tabs can only hold one each
+    fn open_pipeline(&mut self) {
+        let Some(path) = FileDialog::new().add_filter("Pipeline", &["toml"]).pick_file() else {
+            return;
+        };
+        let pipeline = match Pipeline::load(&path) {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                self.pipeline_status = Some(format!("Could not open {}: {}", path.display(), e));
+                return;
+            }
+        };
+        let mut loaded: Vec<TabId> = Vec::new();
+        for step in &pipeline.steps {
+            let tab = step.operation.tab();
+            if loaded.contains(&tab) {
+                continue;
+            }
+            match tab {
+                TabId::CsvProcessing => self.csv_processing_tab.load_step(&step.operation, &mut self.selected_files),
+                TabId::PhoneExtraction => self.phone_extraction_tab.load_step(&step.operation, &mut self.selected_files),
+                TabId::EmailSearch => self.email_search_tab.load_step(&step.operation),
+                TabId::EmailComparison => self.email_comparison_tab.load_step(&step.operation),
+                TabId::Dedupe => self.dedupe_tab.load_step(&step.operation, &mut self.selected_files),
+                TabId::Pipeline => {}
+            }
+            loaded.push(tab);
+        }
+        if let Some(first) = loaded.first() {
+            self.current_tab = match first {
+                TabId::CsvProcessing => Tab::CsvProcessing,
+                TabId::PhoneExtraction => Tab::PhoneExtraction,
+                TabId::EmailSearch => Tab::EmailSearch,
+                TabId::EmailComparison => Tab::EmailComparison,
+                TabId::Dedupe => Tab::Dedupe,
+                TabId::Pipeline => Tab::Pipeline,
+            };
+        }
+        let mut status = format!("Opened {} ({} steps)", path.display(), pipeline.steps.len());
+        if loaded.len() < pipeline.steps.len() {
+            status.push_str("; only the first step of each operation is shown in the tabs");
+        }
+        self.pipeline_status = Some(status);
+        self.pipeline = pipeline;
+        self.pipeline_path = Some(path);
+    }
+
+    // Writes the opened pipeline back with the tabs' current settings, or a new one-step
+    // pipeline for the current tab
+    fn save_pipeline(&mut self) {
+        let mut pipeline = self.pipeline.clone();
+        if pipeline.steps.is_empty() {
+            let tab = match self.current_tab {
+                Tab::CsvProcessing => TabId::CsvProcessing,
+                Tab::PhoneExtraction => TabId::PhoneExtraction,
+                Tab::EmailSearch => TabId::EmailSearch,
+                Tab::EmailComparison => TabId::EmailComparison,
+                Tab::Dedupe => TabId::Dedupe,
+                Tab::Pipeline | Tab::History => {
+                    self.pipeline_status = Some("Choose the tab to save as a pipeline first".to_string());
+                    return;
+                }
+            };
+            pipeline.steps.extend(self.pipeline_operation(tab).map(|operation| PipelineStep { name: String::new(), operation }));
+        } else {
+            let mut updated: Vec<TabId> = Vec::new();
+            for step in &mut pipeline.steps {
+                let tab = step.operation.tab();
+                if !updated.contains(&tab) {
+                    if let Some(operation) = self.pipeline_operation(tab) {
+                        step.operation = operation;
+                    }
+                    updated.push(tab);
+                }
+            }
+        }
+
+        let mut dialog = FileDialog::new().add_filter("Pipeline", &["toml"]).set_file_name("pipeline.toml");
+        if let Some(dir) = self.pipeline_path.as_ref().and_then(|path| path.parent()) {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.save_file() else {
+            return;
+        };
+        match pipeline.save(&path) {
+            Ok(()) => {
+                self.pipeline_status = Some(format!("Saved {} ({} steps)", path.display(), pipeline.steps.len()));
+                self.pipeline = pipeline;
+                self.pipeline_path = Some(path);
+            }
+            Err(e) => self.pipeline_status = Some(format!("Could not save {}: {}", path.display(), e)),
+        }
+    }
+
+    // Switches to the job's tab and starts it again with its recorded settings
+    fn rerun(&mut self, job: &JobRecord) {
+        match job.settings.tab() {
+            TabId::CsvProcessing => {
+                self.current_tab = Tab::CsvProcessing;
+                self.csv_processing_tab.rerun(job, &mut self.selected_files, &self.tx);
+            }
+            TabId::PhoneExtraction => {
+                self.current_tab = Tab::PhoneExtraction;
+                self.phone_extraction_tab.rerun(job, &mut self.selected_files, &self.tx);
+            }
+            TabId::EmailSearch => {
+                self.current_tab = Tab::EmailSearch;
+                self.email_search_tab.rerun(job, &self.tx);
+            }
+            TabId::EmailComparison => {
+                self.current_tab = Tab::EmailComparison;
+                self.email_comparison_tab.rerun(job, &self.tx);
+            }
+            TabId::Dedupe => {
+                self.current_tab = Tab::Dedupe;
+                self.dedupe_tab.rerun(job, &mut self.selected_files, &self.tx);
+            }
+            TabId::Pipeline => {
+                self.current_tab = Tab::Pipeline;
+                self.pipeline_tab.rerun(job, &self.tx);
+            }
+        }
+    }
+
     fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let saved: SavedSettings = cc.storage.and_then(|storage| eframe::get_value(storage, eframe::APP_KEY)).unwrap_or_default();
         let (tx, rx) = channel();
         Self {
             selected_files: Vec::new(),
-            processing_status: String::new(),
             rx,
             tx,
-            theme: Theme::Light,
+            theme: saved.theme,
             current_tab: Tab::EmailSearch, // Changed this line
-            csv_processing_tab: CsvProcessingTab::new(),
-            phone_extraction_tab: PhoneExtractionTab::new(),
-            email_search_tab: EmailSearchTab::new(),
-            email_comparison_tab: EmailComparisonTab::new(), // Add this line
+            csv_processing_tab: CsvProcessingTab::new(saved.csv_processing),
+            phone_extraction_tab: PhoneExtractionTab::new(saved.phone_extraction),
+            email_search_tab: EmailSearchTab::new(saved.email_search),
+            email_comparison_tab: EmailComparisonTab::new(saved.email_comparison), // Add this line
+            dedupe_tab: DedupeTab::new(saved.dedupe),
+            pipeline_tab: PipelineTab::new(saved.pipeline),
+            log_panel: LogPanel::new(),
+            file_logger: FileLogger::new(saved.log_dir.unwrap_or_else(file_log::default_log_dir)),
+            history_tab: HistoryTab::new(history::default_history_path()),
+            pipeline_path: None,
+            pipeline: Pipeline::default(),
+            pipeline_status: None,
         }
     }
 }
 
 fn main() -> Result<(), eframe::Error> {
+    // `csv_processor --run pipeline.toml` runs a pipeline without opening a window
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--run") {
+        let code = match &args[2..] {
+            [path] => match headless::run_pipeline(Path::new(path)) {
+                Ok(0) => 0,
+                Ok(errors) => {
+                    eprintln!("Finished with {} errors", errors);
+                    1
+                }
+                Err(e) => {
+                    eprintln!("Could not run {}: {}", path, e);
+                    2
+                }
+            },
+            _ => {
+                eprintln!("Usage: {} --run <pipeline.toml>", args[0]);
+                2
+            }
+        };
+        std::process::exit(code);
+    }
+
     let native_options = eframe::NativeOptions {
         initial_window_size: Some(egui::vec2(500.0, 600.0)),
         min_window_size: Some(egui::vec2(400.0, 500.0)),
         ..Default::default()
     };
     eframe::run_native(
-        "CSV Processor",
+        APP_NAME,
         native_options,
         Box::new(|cc| Box::new(CsvProcessorApp::new(cc))),
     )
 }
 
-fn process_csv_file(file_path: &PathBuf, states: &[String], email_domains: &[String]) -> Result<(), Box<dyn std::error::Error>> {
-    let file = File::open(file_path)?;
-    let mut rdr = ReaderBuilder::new().has_headers(false).from_reader(file);
-
-    let mut writers: Vec<Writer<File>> = states
-        .iter()
-        .map(|state| {
-            let output_file = File::create(format!("output_{}.csv", state)).unwrap();
-            Writer::from_writer(output_file)
-        })
-        .collect();
-
-    // Process each record
-    for result in rdr.records() {
-        let record = result?;
-        
-        // Check if any column matches any state
-        let state_match = states.iter().enumerate().find(|(_, state)| {
-            record.iter().any(|field| field.trim() == *state)
-        });
+// The display name of a CSV file, possibly inside an archive, and how it was read
+type ProcessedFile = (String, InputEncoding, dialect::Dialect);
 
-        // If a state matches, check for email domain (if specified)
-        if let Some((state_index, _)) = state_match {
-            let email_match = email_domains.is_empty() || email_domains.iter().any(|domain| {
-                record.iter().any(|field| field.to_lowercase().contains(domain))
-            });
+// Where the rows of `state` are written, e.g. `output_NY.csv`
+fn state_output_path(output_dir: &Path, state: &str) -> PathBuf {
+    output_dir.join(format!("output_{}.csv", state))
+}
 
-            if email_match {
-                writers[state_index].write_record(&record)?;
-            }
-        }
+// Creates the output of every state, once for a whole run so each holds the rows of all its inputs.
+// Lenient reading keeps rows with a different number of fields.
+fn create_state_writers(output_dir: &Path, states: &[String]) -> csv::Result<Vec<Writer<File>>> {
+    states.iter().map(|state| WriterBuilder::new().flexible(true).from_path(state_output_path(output_dir, state))).collect()
+}
 
-    }
+// Returns the display name, encoding and dialect of every CSV processed, including entries inside
+// archives. `written` is increased by the rows written to each state's output.
+fn process_csv_file(
+    file_path: &Path,
+    states: &[String],
+    email_domains: &[String],
+    reading: &mut CsvReading,
+    writers: &mut [Writer<File>],
+    written: &mut [u64],
+    progress: &Progress,
+) -> Result<Vec<ProcessedFile>, Box<dyn std::error::Error>> {
+    let processed = split_by_state(file_path, states, email_domains, reading, progress, &mut |state_index, record| {
+        written[state_index] += 1;
+        writers[state_index].write_record(record)
+    });
 
-    // Flush all the writers to make sure data is written to files
-    for mut writer in writers {
+    // Flush all the writers to make sure data is written to files, including a failed file's rows
+    for writer in writers.iter_mut() {
         writer.flush()?;
     }
 
-    Ok(())
+    processed
 }
 
-fn extract_phone_numbers(file_path: &PathBuf) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let file = File::open(file_path)?;
-    let mut rdr = ReaderBuilder::new().has_headers(false).from_reader(file);
+// Calls `on_row` with the index of the matching state for every row that has one of `states` in a
+// column and, when `email_domains` is not empty, one of the domains in a field
+fn split_by_state(
+    file_path: &Path,
+    states: &[String],
+    email_domains: &[String],
+    reading: &mut CsvReading,
+    progress: &Progress,
+    on_row: &mut dyn FnMut(usize, &StringRecord) -> csv::Result<()>,
+) -> Result<Vec<ProcessedFile>, Box<dyn std::error::Error>> {
+    let mut processed = Vec::new();
+
+    archive::visit_file(file_path, progress, &|name| archive::has_extension(name, &["csv"]), &mut |name, reader| {
+        // Process each record
+        let (used_encoding, used_dialect) = reading.read(name, reader, &mut |record| {
+            progress.add_rows(1);
+
+            // Check if any column matches any state
+            let state_match = states.iter().enumerate().find(|(_, state)| {
+                record.iter().any(|field| field.trim() == *state)
+            });
+
+            // If a state matches, check for email domain (if specified)
+            if let Some((state_index, _)) = state_match {
+                let email_match = email_domains.is_empty() || email_domains.iter().any(|domain| {
+                    record.iter().any(|field| field.to_lowercase().contains(domain))
+                });
+
+                if email_match {
+                    on_row(state_index, record)?;
+                }
+            }
+            Ok(())
+        })?;
+
+        processed.push((name.to_string(), used_encoding, used_dialect));
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    Ok(processed)
+}
+
+fn extract_phone_numbers(file_path: &Path, reading: &mut CsvReading, progress: &Progress) -> Result<Vec<String>, Box<dyn std::error::Error>> {
     let phone_regex = Regex::new(r"\(\d{3}\)\s*\d{3}-\d{4}").unwrap();
     let mut phone_numbers = Vec::new();
 
-    for result in rdr.records() {
-        let record = result?;
-        for field in record.iter() {
-            if let Some(phone) = phone_regex.find(field) {
-                let formatted_number = format!("+1{}", phone.as_str().replace(&['(', ')', ' ', '-'][..], ""));
-                phone_numbers.push(formatted_number);
+    archive::visit_file(file_path, progress, &|name| archive::has_extension(name, &["csv"]), &mut |name, reader| {
+        reading.read(name, reader, &mut |record| {
+            progress.add_rows(1);
+            for field in record.iter() {
+                if let Some(phone) = phone_regex.find(field) {
+                    let formatted_number = format!("+1{}", phone.as_str().replace(&['(', ')', ' ', '-'][..], ""));
+                    phone_numbers.push(formatted_number);
+                }
             }
-        }
-    }
+            Ok(())
+        })?;
+        Ok(ControlFlow::Continue(()))
+    })?;
 
     Ok(phone_numbers)
 }
 
-fn save_phone_numbers_to_file(phone_numbers: &[String]) -> Result<(), Box<dyn std::error::Error>> {
-    let mut file = File::create("phone_numbers.txt")?;
+fn save_phone_numbers_to_file(phone_numbers: &[String], path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = File::create(path)?;
     for number in phone_numbers {
         writeln!(file, "{}", number)?;
     }
@@ -0,0 +1,377 @@
+use crate::formats;
+use aho_corasick::{AhoCorasick, MatchKind};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum MatchMode {
+    Exact,
+    Normalized,
+    Contains,
+    Domain,
+    Fuzzy,
+}
+
+impl MatchMode {
+    pub const ALL: [MatchMode; 5] = [
+        MatchMode::Exact,
+        MatchMode::Normalized,
+        MatchMode::Contains,
+        MatchMode::Domain,
+        MatchMode::Fuzzy,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MatchMode::Exact => "Exact",
+            MatchMode::Normalized => "Normalised (ignore dots/+tags)",
+            MatchMode::Contains => "Contains",
+            MatchMode::Domain => "Same domain",
+            MatchMode::Fuzzy => "Fuzzy",
+        }
+    }
+}
+
+// Providers that ignore dots in the local part
+const DOTLESS_DOMAINS: [&str; 2] = ["gmail.com", "googlemail.com"];
+// Providers that deliver `name+tag@` to `name@`
+const PLUS_TAG_DOMAINS: [&str; 9] = [
+    "gmail.com",
+    "googlemail.com",
+    "outlook.com",
+    "hotmail.com",
+    "live.com",
+    "icloud.com",
+    "me.com",
+    "fastmail.com",
+    "protonmail.com",
+];
+
+// Folds provider-specific aliases so `Jon.Smith+news@googlemail.com` becomes `jonsmith@gmail.com`
+pub fn normalize_email(email: &str) -> String {
+    let email = email.trim().to_lowercase();
+    let (local, domain) = match email.rsplit_once('@') {
+        Some(parts) => parts,
+        None => return email,
+    };
+
+    let mut local = local.to_string();
+    if PLUS_TAG_DOMAINS.contains(&domain) {
+        if let Some(index) = local.find('+') {
+            local.truncate(index);
+        }
+    }
+    if DOTLESS_DOMAINS.contains(&domain) {
+        local.retain(|c| c != '.');
+    }
+    let domain = if domain == "googlemail.com" { "gmail.com" } else { domain };
+    format!("{}@{}", local, domain)
+}
+
+// Levenshtein distance over characters
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+fn domain_of(email: &str) -> &str {
+    email.rsplit_once('@').map(|(_, domain)| domain).unwrap_or("")
+}
+
+// Matches fields against a whole email list in the selected mode, so each field is looked at once
+pub struct ListMatcher {
+    mode: MatchMode,
+    max_distance: usize,
+    emails: Vec<String>,
+    lowered: Vec<String>,
+    // Lower-cased email -> positions in `emails`
+    exact: HashMap<String, Vec<usize>>,
+    // Normalised email or domain -> positions, depending on the mode
+    keys: HashMap<String, Vec<usize>>,
+    // Fuzzy mode: positions by the number of characters of the lower-cased email. An email more
+    // characters longer or shorter than the allowed distance cannot be close enough.
+    by_length: Vec<Vec<usize>>,
+    automaton: Option<AhoCorasick>,
+}
+
+impl ListMatcher {
+    pub fn new(emails: &[String], mode: MatchMode, max_distance: usize) -> Result<Self, aho_corasick::BuildError> {
+        let mut seen = HashSet::new();
+        let emails: Vec<String> = emails
+            .iter()
+            .map(|email| email.trim().to_string())
+            .filter(|email| !email.is_empty() && seen.insert(email.to_lowercase()))
+            .collect();
+        let lowered: Vec<String> = emails.iter().map(|email| email.to_lowercase()).collect();
+
+        let mut exact: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut keys: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut by_length: Vec<Vec<usize>> = Vec::new();
+        for (i, email) in lowered.iter().enumerate() {
+            exact.entry(email.clone()).or_default().push(i);
+            match mode {
+                MatchMode::Normalized => keys.entry(normalize_email(email)).or_default().push(i),
+                MatchMode::Domain => keys.entry(domain_of(email).to_string()).or_default().push(i),
+                MatchMode::Fuzzy => {
+                    let length = email.chars().count();
+                    if by_length.len() <= length {
+                        by_length.resize(length + 1, Vec::new());
+                    }
+                    by_length[length].push(i);
+                }
+                _ => {}
+            }
+        }
+
+        // One automaton for the whole list, so a contains search costs the same however many emails there are
+        let automaton = match mode {
+            MatchMode::Contains => Some(
+                AhoCorasick::builder()
+                    .ascii_case_insensitive(true)
+                    .match_kind(MatchKind::Standard)
+                    .build(&emails)?,
+            ),
+            _ => None,
+        };
+
+        Ok(Self { mode, max_distance, emails, lowered, exact, keys, by_length, automaton })
+    }
+
+    pub fn emails(&self) -> &[String] {
+        &self.emails
+    }
+
+    // Whether `bytes` may hold a listed email. A contains search tells in one pass of its automaton
+    // over the raw bytes; the other modes have to look at the fields.
+    pub fn may_match(&self, bytes: &[u8]) -> bool {
+        match &self.automaton {
+            Some(automaton) => automaton.is_match(bytes),
+            None => true,
+        }
+    }
+
+    // Returns each listed email the field matches, with how it matched (e.g. `exact`, `fuzzy:2`)
+    pub fn find(&self, field: &str) -> Vec<(String, String)> {
+        let field = field.trim();
+        let lower = field.to_lowercase();
+        let exact = self.exact.get(&lower).map(Vec::as_slice).unwrap_or_default();
+        let mut found: Vec<(usize, String)> = exact.iter().map(|&i| (i, "exact".to_string())).collect();
+
+        match self.mode {
+            MatchMode::Exact => {}
+            MatchMode::Normalized => {
+                if lower.contains('@') {
+                    for &i in self.keys.get(&normalize_email(&lower)).into_iter().flatten() {
+                        found.push((i, "normalized".to_string()));
+                    }
+                }
+            }
+            MatchMode::Contains => {
+                // Overlapping so `bob@a.com` and `jimbob@a.com` are both found in `jimbob@a.com`
+                if let Some(automaton) = &self.automaton {
+                    for m in automaton.find_overlapping_iter(field) {
+                        found.push((m.pattern().as_usize(), "contains".to_string()));
+                    }
+                }
+            }
+            MatchMode::Domain => {
+                // The field may hold more than the address, e.g. `Contact: a@b.com`
+                let domains: HashSet<&str> = lower
+                    .split(|c: char| c.is_whitespace() || ",;<>\"'()[]:".contains(c))
+                    .filter(|token| token.contains('@'))
+                    .map(domain_of)
+                    .collect();
+                for domain in domains {
+                    for &i in self.keys.get(domain).into_iter().flatten() {
+                        found.push((i, "domain".to_string()));
+                    }
+                }
+            }
+            MatchMode::Fuzzy => {
+                if lower.contains('@') {
+                    let length = lower.chars().count();
+                    let mut candidates: Vec<usize> = (length.saturating_sub(self.max_distance)..=length + self.max_distance)
+                        .filter_map(|length| self.by_length.get(length))
+                        .flatten()
+                        .copied()
+                        .collect();
+                    // In list order, as without the index
+                    candidates.sort_unstable();
+                    for i in candidates {
+                        let distance = edit_distance(&lower, &self.lowered[i]);
+                        if distance <= self.max_distance {
+                            found.push((i, format!("fuzzy:{}", distance)));
+                        }
+                    }
+                }
+            }
+        }
+
+        // An exact match is reported as such, even in the looser modes
+        let mut reported = HashSet::new();
+        found
+            .into_iter()
+            .filter(|(i, _)| reported.insert(*i))
+            .map(|(i, match_type)| (self.emails[i].clone(), match_type))
+            .collect()
+    }
+}
+
+// What a search looks for: the addresses in a list, or any address matching a domain or pattern
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum QueryMode {
+    EmailList,
+    Domains,
+    Regex,
+}
+
+impl QueryMode {
+    pub const ALL: [QueryMode; 3] = [QueryMode::EmailList, QueryMode::Domains, QueryMode::Regex];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            QueryMode::EmailList => "Email list",
+            QueryMode::Domains => "Domains",
+            QueryMode::Regex => "Regex pattern",
+        }
+    }
+}
+
+// Finds the listed emails in a field, or every address that belongs to one of the domains or matches the pattern
+pub enum SearchQuery {
+    List(ListMatcher),
+    Domains { domains: Vec<String>, email_regex: Regex },
+    Regex(Regex),
+}
+
+impl SearchQuery {
+    // `acme.com` also matches subdomains such as `mail.acme.com`
+    pub fn domains(domains: &[String]) -> Self {
+        let domains = domains.iter().map(|domain| domain.trim().trim_start_matches('@').to_lowercase()).filter(|domain| !domain.is_empty()).collect();
+        SearchQuery::Domains { domains, email_regex: formats::email_regex() }
+    }
+
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(SearchQuery::Regex(RegexBuilder::new(pattern).case_insensitive(true).build()?))
+    }
+
+    // The list that a not-found report is written for
+    pub fn email_list(&self) -> Option<&[String]> {
+        match self {
+            SearchQuery::List(matcher) => Some(matcher.emails()),
+            _ => None,
+        }
+    }
+
+    // Whether searching the raw bytes of a file first can rule it out
+    pub fn scans_bytes(&self) -> bool {
+        matches!(self, SearchQuery::List(matcher) if matcher.automaton.is_some())
+    }
+
+    pub fn may_match(&self, bytes: &[u8]) -> bool {
+        match self {
+            SearchQuery::List(matcher) => matcher.may_match(bytes),
+            _ => true,
+        }
+    }
+
+    // Returns each matching address with how it matched, e.g. `domain:acme.com`
+    pub fn find(&self, field: &str) -> Vec<(String, String)> {
+        match self {
+            SearchQuery::List(matcher) => matcher.find(field),
+            SearchQuery::Domains { domains, email_regex } => email_regex
+                .find_iter(field)
+                .filter_map(|m| {
+                    let address = m.as_str().to_lowercase();
+                    let domain = domain_of(&address);
+                    let listed = domains.iter().find(|listed| domain == listed.as_str() || domain.ends_with(&format!(".{}", listed)))?;
+                    Some((address.clone(), format!("domain:{}", listed)))
+                })
+                .collect(),
+            SearchQuery::Regex(regex) => regex.find_iter(field).map(|m| (m.as_str().to_string(), "regex".to_string())).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(emails: &[&str]) -> Vec<String> {
+        emails.iter().map(|email| email.to_string()).collect()
+    }
+
+    #[test]
+    fn folds_gmail_dots_and_plus_tags() {
+        assert_eq!(normalize_email("Jon.Smith+news@googlemail.com"), "jonsmith@gmail.com");
+        assert_eq!(normalize_email(" j.o.n@gmail.com "), "jon@gmail.com");
+    }
+
+    #[test]
+    fn keeps_what_other_providers_deliver_separately() {
+        // Plus tags are only folded for providers known to deliver them to the same mailbox
+        assert_eq!(normalize_email("jon+news@example.com"), "jon+news@example.com");
+        assert_eq!(normalize_email("jon.smith+news@outlook.com"), "jon.smith@outlook.com");
+        assert_eq!(normalize_email("not an email"), "not an email");
+    }
+
+    #[test]
+    fn normalized_mode_finds_aliases() {
+        let matcher = ListMatcher::new(&list(&["jonsmith@gmail.com", "jon@example.com"]), MatchMode::Normalized, 0).unwrap();
+        assert_eq!(matcher.find("Jon.Smith+x@googlemail.com"), vec![("jonsmith@gmail.com".to_string(), "normalized".to_string())]);
+        assert_eq!(matcher.find("JON@example.com"), vec![("jon@example.com".to_string(), "exact".to_string())]);
+        assert!(matcher.find("jon+x@example.com").is_empty());
+    }
+
+    #[test]
+    fn counts_edits_in_characters() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("josé@a.com", "jose@a.com"), 1);
+        assert_eq!(edit_distance("søren@a.dk", "sren@a.dk"), 1);
+    }
+
+    #[test]
+    fn fuzzy_mode_reports_matches_within_the_distance() {
+        let matcher = ListMatcher::new(&list(&["jon@example.com", "jane@example.com", "søren@example.dk"]), MatchMode::Fuzzy, 1).unwrap();
+        assert_eq!(matcher.find("jon@example.com"), vec![("jon@example.com".to_string(), "exact".to_string())]);
+        assert_eq!(matcher.find("joan@example.com"), vec![("jon@example.com".to_string(), "fuzzy:1".to_string())]);
+        assert!(matcher.find("joann@example.com").is_empty());
+        // One character more, though two bytes fewer
+        assert_eq!(matcher.find("sren@example.dk"), vec![("søren@example.dk".to_string(), "fuzzy:1".to_string())]);
+    }
+
+    #[test]
+    fn fuzzy_index_finds_what_a_full_scan_finds_in_list_order() {
+        let emails = list(&["ann@a.com", "anne@a.com", "an@a.com", "bob@b.org", "annie@a.com", "ANN@a.com", "ånn@a.com", "x@y.z"]);
+        for max_distance in 0..=3 {
+            let matcher = ListMatcher::new(&emails, MatchMode::Fuzzy, max_distance).unwrap();
+            for field in ["ann@a.com", "anne@a.com", "ann@a.co", "bob@b.org", "ånne@a.com", "nobody@example.com"] {
+                let lower = field.to_lowercase();
+                let mut expected: Vec<(String, String)> = Vec::new();
+                let listed = matcher.emails();
+                for email in listed.iter().filter(|email| email.to_lowercase() == lower) {
+                    expected.push((email.clone(), "exact".to_string()));
+                }
+                for email in listed {
+                    let distance = edit_distance(&lower, &email.to_lowercase());
+                    if distance <= max_distance && !expected.iter().any(|(found, _)| found == email) {
+                        expected.push((email.clone(), format!("fuzzy:{}", distance)));
+                    }
+                }
+                assert_eq!(matcher.find(field), expected, "{} within {}", field, max_distance);
+            }
+        }
+    }
+}
@@ -1,22 +1,76 @@
 use eframe::egui;
 use std::path::PathBuf;
 use std::sync::mpsc::Sender;
+use std::sync::Arc;
 use std::thread;
+use std::time::Instant;
 use egui::RichText;
 use rfd::FileDialog;
+use crate::archive;
+use crate::dialect::{self, DialectOptions};
+use crate::encoding::{self, InputEncoding};
+use crate::events::{Event, EventKind, EventSender, Summary, TabId, TabStatus};
+use crate::history::{self, InputFile, JobDetails, JobRecord, JobSettings, OutputFile};
+use crate::pipeline::{FileOutput, Operation};
+use crate::presets::PresetPicker;
+use crate::records::{self, CsvReading};
+use crate::preview::PreviewPane;
+use crate::progress::{self, Progress};
+use serde::{Deserialize, Serialize};
 
-pub struct PhoneExtractionTab;
+// What can be saved as a preset and is restored on launch
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhoneExtractionSettings {
+    pub encoding: InputEncoding,
+    pub dialect: DialectOptions,
+    // Keep going past malformed rows, listing them in `<name>_bad_records.csv` next to the output file
+    pub lenient: bool,
+    pub output_file: PathBuf,
+}
+
+impl Default for PhoneExtractionSettings {
+    fn default() -> Self {
+        Self { encoding: InputEncoding::Auto, dialect: DialectOptions::default(), lenient: false, output_file: history::default_phone_output() }
+    }
+}
+
+pub struct PhoneExtractionTab {
+    settings: PhoneExtractionSettings,
+    presets: PresetPicker,
+    preview: PreviewPane,
+    progress: Arc<Progress>,
+    status: TabStatus,
+}
 
 impl PhoneExtractionTab {
-    pub fn new() -> Self {
-        Self
+    pub fn new(settings: PhoneExtractionSettings) -> Self {
+        Self {
+            settings,
+            presets: PresetPicker::new("phone_extraction"),
+            preview: PreviewPane::new(),
+            progress: Arc::new(Progress::new()),
+            status: TabStatus::new(),
+        }
     }
 
-    pub fn ui(&mut self, ui: &mut egui::Ui, selected_files: &mut Vec<PathBuf>, processing_status: &mut String, tx: &Sender<String>) {
+    pub fn handle_event(&mut self, event: EventKind) {
+        self.status.apply(event);
+    }
+
+    pub fn settings(&self) -> &PhoneExtractionSettings {
+        &self.settings
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, selected_files: &mut Vec<PathBuf>, tx: &Sender<Event>) {
+        self.presets.ui(ui, &mut self.settings);
+        ui.add_space(10.0);
+
         ui.horizontal(|ui| {
             if ui.button(RichText::new("📁 Select CSV Files").size(18.0)).clicked() {
                 if let Some(files) = FileDialog::new()
                     .add_filter("CSV", &["csv"])
+                    .add_filter("Archives", &archive::ARCHIVE_EXTENSIONS)
                     .set_directory("/")
                     .pick_files()
                 {
@@ -25,42 +79,121 @@ impl PhoneExtractionTab {
             }
             ui.label(RichText::new(format!("Selected files: {}", selected_files.len())).size(16.0));
         });
+        self.preview.ui(ui, "phone_extraction_preview", selected_files, self.settings.encoding, &self.settings.dialect.header_if_chosen());
 
-        ui.add_space(20.0);
+        ui.add_space(10.0);
 
-        if ui.add_sized([ui.available_width(), 40.0], egui::Button::new(RichText::new("📞 Extract Phone Numbers").size(20.0))).clicked() {
-            let files = selected_files.clone();
-            let tx = tx.clone();
-            thread::spawn(move || {
-                let mut all_phone_numbers = Vec::new();
-                for file in files {
-                    match crate::extract_phone_numbers(&file) {
-                        Ok(numbers) => {
-                            all_phone_numbers.extend(numbers);
-                            tx.send(format!("Extracted phone numbers from: {}", file.display())).unwrap();
-                        }
-                        Err(e) => {
-                            tx.send(format!("Error processing {}: {}", file.display(), e)).unwrap();
-                        }
-                    }
-                }
-                if let Err(e) = crate::save_phone_numbers_to_file(&all_phone_numbers) {
-                    tx.send(format!("Error saving phone numbers: {}", e)).unwrap();
-                } else {
-                    tx.send("Phone numbers extracted and saved to 'phone_numbers.txt'".to_string()).unwrap();
+        encoding::encoding_selector(ui, "phone_extraction_encoding", &mut self.settings.encoding);
+        dialect::dialect_selector(ui, "phone_extraction_dialect", &mut self.settings.dialect, false);
+        let bad_records = records::bad_records_path(&self.settings.output_file);
+        ui.checkbox(&mut self.settings.lenient, format!("Lenient: skip or repair malformed rows and list them in {}", bad_records.display()));
+        ui.horizontal(|ui| {
+            if ui.button("Output File").clicked() {
+                if let Some(path) = FileDialog::new().add_filter("Text file", &["txt"]).set_file_name("phone_numbers.txt").save_file() {
+                    self.settings.output_file = path;
                 }
-            });
+            }
+            ui.label(format!("Output file: {}", self.settings.output_file.display()));
+        });
+
+        ui.add_space(20.0);
+
+        let running = self.progress.is_running();
+        if ui.add_enabled_ui(!running, |ui| ui.add_sized([ui.available_width(), 40.0], egui::Button::new(RichText::new("📞 Extract Phone Numbers").size(20.0)))).inner.clicked() {
+            self.start(selected_files.clone(), tx);
         }
 
         ui.add_space(10.0);
+        progress::progress_panel(ui, &self.progress);
 
         // Display processing status
-        if !processing_status.is_empty() {
-            ui.horizontal(|ui| {
-                ui.label(RichText::new("Status:").strong());
-                ui.label(&*processing_status);
+        self.status.ui(ui);
+    }
 
-            });
+    pub fn pipeline_step(&self, selected_files: &[PathBuf]) -> Operation {
+        Operation::PhoneExtraction {
+            inputs: selected_files.to_vec(),
+            encoding: self.settings.encoding,
+            dialect: self.settings.dialect,
+            lenient: self.settings.lenient,
+            outputs: FileOutput { file: self.settings.output_file.clone() },
         }
     }
-}
\ No newline at end of file
+
+    pub fn load_step(&mut self, operation: &Operation, selected_files: &mut Vec<PathBuf>) {
+        let Operation::PhoneExtraction { inputs, encoding, dialect, lenient, outputs } = operation else {
+            return;
+        };
+        self.settings.encoding = *encoding;
+        self.settings.dialect = *dialect;
+        self.settings.lenient = *lenient;
+        self.settings.output_file = outputs.file.clone();
+        *selected_files = inputs.clone();
+    }
+
+    // Runs a job from the history again, on the same files
+    pub fn rerun(&mut self, job: &JobRecord, selected_files: &mut Vec<PathBuf>, tx: &Sender<Event>) {
+        let JobSettings::PhoneExtraction { encoding, dialect, lenient, output_file } = &job.settings else {
+            return;
+        };
+        if self.progress.is_running() {
+            self.status.set("Phone numbers are still being extracted");
+            return;
+        }
+        self.settings.encoding = *encoding;
+        self.settings.dialect = *dialect;
+        self.settings.lenient = *lenient;
+        self.settings.output_file = output_file.clone();
+        *selected_files = job.inputs.iter().map(|input| input.path.clone()).collect();
+        self.start(selected_files.clone(), tx);
+    }
+
+    fn start(&mut self, files: Vec<PathBuf>, tx: &Sender<Event>) {
+        let events = EventSender::new(TabId::PhoneExtraction, tx);
+        let progress = self.progress.clone();
+        progress.start(&files);
+        let settings = self.settings.clone();
+        thread::spawn(move || extract_from_files(&files, &settings, &events, &progress));
+    }
+}
+
+// Collects the phone numbers of `files` into `output_file`, for the tab or a pipeline step.
+// `progress` has already been started by the caller.
+pub fn extract_from_files(files: &[PathBuf], settings: &PhoneExtractionSettings, events: &EventSender, progress: &Progress) {
+    let PhoneExtractionSettings { encoding, dialect, lenient, output_file } = settings.clone();
+    let started = Instant::now();
+    let mut errors = 0;
+    let mut all_phone_numbers = Vec::new();
+    events.started(format!("Extracting phone numbers from {} files...", files.len()));
+    let inputs = files.iter().map(|file| InputFile::hash(file)).collect();
+    let mut reading = CsvReading::new(encoding, dialect.header_if_chosen(), lenient, Some(&records::bad_records_path(&output_file)), events);
+    for file in files {
+        progress.set_current_file(&file.display().to_string());
+        match crate::extract_phone_numbers(file, &mut reading, progress) {
+            Ok(numbers) => {
+                events.file_done(file.display().to_string(), format!("{} phone numbers", numbers.len()));
+                all_phone_numbers.extend(numbers);
+            }
+            Err(e) => {
+                errors += 1;
+                events.file_error(&file.display().to_string(), format!("Error processing {}: {}", file.display(), e));
+            }
+        }
+        progress.file_done();
+    }
+    progress.finish();
+    let mut outputs: Vec<OutputFile> = reading.finish(events).into_iter().collect();
+    let message = match crate::save_phone_numbers_to_file(&all_phone_numbers, &output_file) {
+        Ok(()) => {
+            outputs.push(OutputFile { path: output_file.clone(), rows: all_phone_numbers.len() as u64 });
+            format!("Phone numbers extracted and saved to '{}'", output_file.display())
+        }
+        Err(e) => {
+            errors += 1;
+            events.error(format!("Error saving phone numbers: {}", e));
+            "Phone numbers could not be saved".to_string()
+        }
+    };
+    let details = JobDetails { settings: JobSettings::PhoneExtraction { encoding, dialect, lenient, output_file }, inputs, outputs, rows: progress.snapshot().rows };
+    events.finished(Summary { message, files: files.len(), errors, elapsed: started.elapsed(), details });
+}
@@ -0,0 +1,261 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use crate::email_search::{self, EmailSearchPreset, EmailSearchSettings, SearchJob};
+use crate::dialect::DialectOptions;
+use crate::encoding::InputEncoding;
+use crate::events::{Event, EventSender, TabId};
+use crate::progress::Progress;
+use crate::csv_processing::{self, CsvProcessingSettings};
+use crate::dedupe::{self, DedupeKey, DedupeSettings, MergeStrategy};
+use crate::phone_extraction::{self, PhoneExtractionSettings};
+use crate::{email_comparison, history};
+
+// A job definition that can be kept under version control, e.g.
+//
+//     [[step]]
+//     name = "Split by state"
+//     operation = "csv_processing"
+//     inputs = ["exports/contacts.csv"]
+//
+//     [step.filters]
+//     states = ["NY", "OH"]
+//     email_domains = ["@gmail.com"]
+//
+//     [step.outputs]
+//     dir = "split"
+//
+// Relative paths are relative to the folder of the pipeline file.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Pipeline {
+    #[serde(default, rename = "step")]
+    pub steps: Vec<PipelineStep>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PipelineStep {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(flatten)]
+    pub operation: Operation,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum Operation {
+    CsvProcessing {
+        inputs: Vec<PathBuf>,
+        #[serde(default)]
+        encoding: InputEncoding,
+        #[serde(default, skip_serializing_if = "DialectOptions::is_auto")]
+        dialect: DialectOptions,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        lenient: bool,
+        filters: CsvFilters,
+        #[serde(default)]
+        outputs: DirOutput,
+    },
+    PhoneExtraction {
+        inputs: Vec<PathBuf>,
+        #[serde(default)]
+        encoding: InputEncoding,
+        #[serde(default, skip_serializing_if = "DialectOptions::is_auto")]
+        dialect: DialectOptions,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        lenient: bool,
+        #[serde(default = "phone_output")]
+        outputs: FileOutput,
+    },
+    // `inputs` holds the two lists to compare
+    EmailComparison {
+        inputs: Vec<PathBuf>,
+        #[serde(default)]
+        encoding: InputEncoding,
+        outputs: FileOutput,
+    },
+    // `inputs` holds the folder to search
+    EmailSearch {
+        inputs: Vec<PathBuf>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        email_list: Option<PathBuf>,
+        #[serde(default)]
+        filters: Box<EmailSearchSettings>,
+        #[serde(default)]
+        outputs: SearchOutputs,
+    },
+    Dedupe {
+        inputs: Vec<PathBuf>,
+        #[serde(default)]
+        encoding: InputEncoding,
+        #[serde(default, skip_serializing_if = "DialectOptions::is_auto")]
+        dialect: DialectOptions,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        lenient: bool,
+        #[serde(default)]
+        keys: DedupeKeys,
+        #[serde(default)]
+        strategy: MergeStrategy,
+        outputs: FileOutput,
+    },
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct CsvFilters {
+    pub states: Vec<String>,
+    #[serde(default)]
+    pub email_domains: Vec<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct DedupeKeys {
+    pub key: DedupeKey,
+    // 1-based columns holding the emails and the phone numbers, every column when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub email_columns: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub phone_columns: Vec<usize>,
+    // 1-based columns holding the name, which must also match
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub name_columns: Vec<usize>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct DirOutput {
+    #[serde(default)]
+    pub dir: PathBuf,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FileOutput {
+    pub file: PathBuf,
+}
+
+fn phone_output() -> FileOutput {
+    FileOutput { file: history::default_phone_output() }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SearchOutputs {
+    // A timestamped file in the current folder when not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub results_file: Option<PathBuf>,
+}
+
+impl Operation {
+    pub fn tab(&self) -> TabId {
+        match self {
+            Operation::CsvProcessing { .. } => TabId::CsvProcessing,
+            Operation::PhoneExtraction { .. } => TabId::PhoneExtraction,
+            Operation::EmailComparison { .. } => TabId::EmailComparison,
+            Operation::EmailSearch { .. } => TabId::EmailSearch,
+            Operation::Dedupe { .. } => TabId::Dedupe,
+        }
+    }
+
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        match self {
+            Operation::CsvProcessing { inputs, outputs, .. } => inputs.iter_mut().chain([&mut outputs.dir]).collect(),
+            Operation::PhoneExtraction { inputs, outputs, .. } | Operation::EmailComparison { inputs, outputs, .. } | Operation::Dedupe { inputs, outputs, .. } => {
+                inputs.iter_mut().chain([&mut outputs.file]).collect()
+            }
+            Operation::EmailSearch { inputs, email_list, outputs, .. } => {
+                inputs.iter_mut().chain(email_list.as_mut()).chain(outputs.results_file.as_mut()).collect()
+            }
+        }
+    }
+}
+
+impl Pipeline {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut pipeline: Pipeline = toml::from_str(&fs::read_to_string(path)?)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        for step in &mut pipeline.steps {
+            for path in step.operation.paths_mut() {
+                if path.is_relative() {
+                    *path = base.join(&*path);
+                }
+            }
+        }
+        Ok(pipeline)
+    }
+
+    // Paths inside the pipeline's folder are written relative to it, so the folder can be moved as a whole
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let mut pipeline = self.clone();
+        let base = path.parent().map(|dir| dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf())).unwrap_or_default();
+        for step in &mut pipeline.steps {
+            for path in step.operation.paths_mut() {
+                let absolute = path.canonicalize().unwrap_or_else(|_| path.clone());
+                if let Ok(relative) = absolute.strip_prefix(&base) {
+                    *path = relative.to_path_buf();
+                }
+            }
+        }
+        fs::write(path, toml::to_string_pretty(&pipeline)?)?;
+        Ok(())
+    }
+}
+
+// Runs one step to completion on the calling thread, reporting through `tx` like the tabs do
+pub fn run_step(step: &PipelineStep, tx: &Sender<Event>) -> Result<(), String> {
+    let events = EventSender::new(step.operation.tab(), tx);
+    match &step.operation {
+        Operation::CsvProcessing { inputs, encoding, dialect, lenient, filters, outputs } => {
+            let progress = Progress::new();
+            progress.start(inputs);
+            let settings = CsvProcessingSettings {
+                states: filters.states.join(","),
+                email_domains: filters.email_domains.join(","),
+                encoding: *encoding,
+                dialect: *dialect,
+                lenient: *lenient,
+                output_dir: outputs.dir.clone(),
+            };
+            csv_processing::process_files(inputs, &settings, &events, &progress);
+        }
+        Operation::PhoneExtraction { inputs, encoding, dialect, lenient, outputs } => {
+            let progress = Progress::new();
+            progress.start(inputs);
+            let settings = PhoneExtractionSettings { encoding: *encoding, dialect: *dialect, lenient: *lenient, output_file: outputs.file.clone() };
+            phone_extraction::extract_from_files(inputs, &settings, &events, &progress);
+        }
+        Operation::EmailComparison { inputs, encoding, outputs } => {
+            let [first, second] = inputs.as_slice() else {
+                return Err(format!("An email comparison needs two input lists, not {}", inputs.len()));
+            };
+            email_comparison::compare_files(first.clone(), second.clone(), outputs.file.clone(), *encoding, &events);
+        }
+        Operation::EmailSearch { inputs, email_list, filters, outputs } => {
+            let [folder] = inputs.as_slice() else {
+                return Err(format!("An email search needs one input folder, not {}", inputs.len()));
+            };
+            let emails = match email_list {
+                Some(path) => Some(email_search::read_email_list(path, filters.encoding).map_err(|e| format!("Could not read the email list {}: {}", path.display(), e))?),
+                None => None,
+            };
+            let preset = EmailSearchPreset {
+                folder: Some(folder.clone()),
+                email_list: email_list.clone(),
+                results_file: outputs.results_file.clone(),
+                settings: (**filters).clone(),
+            };
+            SearchJob::new(&preset, emails.as_deref(), None, None, events)?.run();
+        }
+        Operation::Dedupe { inputs, encoding, dialect, lenient, keys, strategy, outputs } => {
+            let progress = Progress::new();
+            progress.start(inputs);
+            let mut settings = DedupeSettings {
+                strategy: *strategy,
+                encoding: *encoding,
+                dialect: *dialect,
+                lenient: *lenient,
+                output_file: outputs.file.clone(),
+                ..Default::default()
+            };
+            settings.set_keys(keys);
+            dedupe::dedupe_files(inputs, &settings, &events, &progress);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,542 @@
+use eframe::egui;
+use egui::RichText;
+use rfd::FileDialog;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+use csv::StringRecord;
+use crate::archive;
+use crate::dialect::{self, DialectOptions};
+use crate::email_comparison;
+use crate::email_search::{EmailSearchPreset, EmailSearchSettings, SearchJob};
+use crate::encoding::{self, InputEncoding};
+use crate::events::{Event, EventKind, EventSender, Summary, TabId, TabStatus};
+use crate::formats;
+use crate::history::{InputFile, JobDetails, JobRecord, JobSettings, OutputFile};
+use crate::job::JobControl;
+use crate::matching::{MatchMode, QueryMode};
+use crate::presets::PresetPicker;
+use crate::progress::{self, Progress};
+use crate::records::{self, CsvReading};
+use crate::results_table::{ResultsTable, SearchMatch};
+
+// How many rows or emails each step keeps for its preview
+const PREVIEW_ROWS: usize = 20;
+
+// Split by state -> extract the emails of one state -> drop those on a suppression list ->
+// search a folder for the rest. Each step hands its output to the next in memory.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainSettings {
+    pub inputs: Vec<PathBuf>,
+    pub encoding: InputEncoding,
+    pub dialect: DialectOptions,
+    // Keep going past malformed rows, listing them in `bad_records_file` or only counting them
+    pub lenient: bool,
+    pub bad_records_file: Option<PathBuf>,
+    pub states: String,
+    pub email_domains: String,
+    pub extract_state: String,
+    // Without a list every extracted email is kept
+    pub suppression_list: Option<PathBuf>,
+    // Where the emails left after step 3 are saved, if anywhere
+    pub remainder_file: Option<PathBuf>,
+    // Without a folder the pipeline ends after the suppression list
+    pub archive_folder: Option<PathBuf>,
+    pub results_file: Option<PathBuf>,
+    pub search: EmailSearchSettings,
+}
+
+impl Default for ChainSettings {
+    fn default() -> Self {
+        Self {
+            inputs: Vec::new(),
+            encoding: InputEncoding::Auto,
+            dialect: DialectOptions::default(),
+            lenient: false,
+            bad_records_file: None,
+            states: "NY,OH,PA,WA,AK".to_string(),
+            email_domains: String::new(),
+            extract_state: "NY".to_string(),
+            suppression_list: None,
+            remainder_file: None,
+            archive_folder: None,
+            results_file: None,
+            search: EmailSearchSettings::default(),
+        }
+    }
+}
+
+fn split_list(text: &str) -> Vec<String> {
+    text.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+}
+
+// What the steps have produced so far. Only the first rows of each state are kept; the rows of
+// the extracted state go straight to the next step.
+#[derive(Default)]
+struct StepOutputs {
+    split: Vec<(String, usize, Vec<StringRecord>)>,
+    extracted: Option<Vec<String>>,
+    remainder: Option<Vec<String>>,
+    search: Option<SearchRun>,
+}
+
+struct SearchRun {
+    progress: Arc<Progress>,
+    control: Arc<JobControl>,
+    matches: Arc<Mutex<Vec<SearchMatch>>>,
+}
+
+pub struct PipelineTab {
+    settings: ChainSettings,
+    presets: PresetPicker,
+    progress: Arc<Progress>,
+    outputs: Arc<Mutex<StepOutputs>>,
+    results_table: ResultsTable,
+    running: bool,
+    // Marked finished when the chain's thread ends, even by a panic
+    control: Arc<JobControl>,
+    status: TabStatus,
+}
+
+impl PipelineTab {
+    pub fn new(settings: ChainSettings) -> Self {
+        Self {
+            settings,
+            presets: PresetPicker::new("pipeline"),
+            progress: Arc::new(Progress::new()),
+            outputs: Arc::new(Mutex::new(StepOutputs::default())),
+            results_table: ResultsTable::new(),
+            running: false,
+            control: Arc::new(JobControl::new()),
+            status: TabStatus::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: EventKind) {
+        if matches!(event, EventKind::Finished(_)) {
+            self.running = false;
+        }
+        self.status.apply(event);
+    }
+
+    pub fn settings(&self) -> &ChainSettings {
+        &self.settings
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, tx: &Sender<Event>) {
+        if self.running && self.control.is_finished() {
+            self.running = false;
+        }
+        self.presets.ui(ui, &mut self.settings);
+        ui.add_space(10.0);
+        ui.add_enabled_ui(!self.running, |ui| self.settings_ui(ui));
+        ui.add_space(10.0);
+
+        let search = self.outputs.lock().unwrap().search.as_ref().map(|search| (search.progress.clone(), search.control.clone()));
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!self.running, egui::Button::new(RichText::new("▶ Run Pipeline").size(18.0))).clicked() {
+                match self.start(tx) {
+                    Ok(()) => self.status.set("Pipeline running..."),
+                    Err(message) => self.status.set(message),
+                }
+            }
+            // Only the search can be stopped part-way
+            if let Some((_, control)) = &search {
+                if self.running && !control.is_cancelled() && ui.button("⏹ Cancel search").clicked() {
+                    control.cancel();
+                }
+            }
+        });
+        match &search {
+            Some((search_progress, _)) => progress::progress_panel(ui, search_progress),
+            None => progress::progress_panel(ui, &self.progress),
+        }
+        self.status.ui(ui);
+        self.previews_ui(ui);
+    }
+
+    fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        let settings = &mut self.settings;
+        ui.label(RichText::new("1. Split by state").strong());
+        ui.horizontal(|ui| {
+            if ui.button("📁 Select CSV Files").clicked() {
+                if let Some(files) = FileDialog::new().add_filter("CSV", &["csv"]).add_filter("Archives", &archive::ARCHIVE_EXTENSIONS).pick_files() {
+                    settings.inputs = files;
+                }
+            }
+            ui.label(format!("Selected files: {}", settings.inputs.len()));
+        });
+        ui.horizontal(|ui| {
+            ui.label("States:");
+            ui.add(egui::TextEdit::singleline(&mut settings.states).hint_text("NY, OH, PA").desired_width(150.0));
+            ui.label("Email domains:");
+            ui.add(egui::TextEdit::singleline(&mut settings.email_domains).hint_text("empty for all").desired_width(150.0));
+        });
+        encoding::encoding_selector(ui, "pipeline_encoding", &mut settings.encoding);
+        dialect::dialect_selector(ui, "pipeline_dialect", &mut settings.dialect, false);
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut settings.lenient, "Lenient: skip or repair malformed rows");
+            if settings.lenient {
+                if ui.button("Save Bad Records To").clicked() {
+                    if let Some(path) = FileDialog::new().add_filter("CSV", &["csv"]).set_file_name(records::BAD_RECORDS_FILE).save_file() {
+                        settings.bad_records_file = Some(path);
+                    }
+                }
+                match &settings.bad_records_file {
+                    Some(path) => {
+                        ui.label(path.display().to_string());
+                        if ui.small_button("Clear").clicked() {
+                            settings.bad_records_file = None;
+                        }
+                    }
+                    None => {
+                        ui.label("Only counted");
+                    }
+                }
+            }
+        });
+
+        ui.label(RichText::new("2. Extract emails").strong());
+        ui.horizontal(|ui| {
+            ui.label("From the rows of:");
+            egui::ComboBox::from_id_source("pipeline_extract_state")
+                .selected_text(settings.extract_state.as_str())
+                .show_ui(ui, |ui| {
+                    for state in split_list(&settings.states) {
+                        ui.selectable_value(&mut settings.extract_state, state.clone(), state);
+                    }
+                });
+        });
+
+        ui.label(RichText::new("3. Remove suppressed emails").strong());
+        ui.horizontal(|ui| {
+            if ui.button("Select Suppression List").clicked() {
+                if let Some(path) = FileDialog::new().add_filter("Text file", &["txt"]).pick_file() {
+                    settings.suppression_list = Some(path);
+                }
+            }
+            match &settings.suppression_list {
+                Some(path) => {
+                    ui.label(path.display().to_string());
+                    if ui.small_button("Clear").clicked() {
+                        settings.suppression_list = None;
+                    }
+                }
+                None => {
+                    ui.label("None: every extracted email is kept");
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Save Remaining Emails To").clicked() {
+                if let Some(path) = FileDialog::new().add_filter("Text file", &["txt"]).set_file_name("remaining_emails.txt").save_file() {
+                    settings.remainder_file = Some(path);
+                }
+            }
+            match &settings.remainder_file {
+                Some(path) => {
+                    ui.label(path.display().to_string());
+                    if ui.small_button("Clear").clicked() {
+                        settings.remainder_file = None;
+                    }
+                }
+                None => {
+                    ui.label("Not saved");
+                }
+            }
+        });
+
+        ui.label(RichText::new("4. Search a folder for the remaining emails").strong());
+        ui.horizontal(|ui| {
+            if ui.button("Select Folder").clicked() {
+                if let Some(folder) = FileDialog::new().pick_folder() {
+                    settings.archive_folder = Some(folder);
+                }
+            }
+            match &settings.archive_folder {
+                Some(path) => {
+                    ui.label(path.display().to_string());
+                    if ui.small_button("Clear").clicked() {
+                        settings.archive_folder = None;
+                    }
+                }
+                None => {
+                    ui.label("None: the pipeline stops after step 3");
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Select Results File").clicked() {
+                if let Some(path) = FileDialog::new().add_filter("CSV", &["csv"]).save_file() {
+                    settings.results_file = Some(path);
+                }
+            }
+            match &settings.results_file {
+                Some(path) => {
+                    ui.label(format!("Results file: {}", path.display()));
+                    if ui.small_button("Use default").clicked() {
+                        settings.results_file = None;
+                    }
+                }
+                None => {
+                    ui.label("Results file: timestamped file in the current folder");
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Match:");
+            egui::ComboBox::from_id_source("pipeline_match_mode")
+                .selected_text(settings.search.match_mode.label())
+                .show_ui(ui, |ui| {
+                    for mode in MatchMode::ALL {
+                        ui.selectable_value(&mut settings.search.match_mode, mode, mode.label());
+                    }
+                });
+            ui.label("File types:");
+            ui.add(egui::TextEdit::singleline(&mut settings.search.file_types).desired_width(100.0));
+            ui.label("Columns:");
+            ui.add(egui::TextEdit::singleline(&mut settings.search.columns).hint_text("empty for all").desired_width(60.0));
+        });
+    }
+
+    fn previews_ui(&mut self, ui: &mut egui::Ui) {
+        let outputs = self.outputs.clone();
+        let outputs = outputs.lock().unwrap();
+        if !outputs.split.is_empty() {
+            let counts: Vec<String> = outputs.split.iter().map(|(state, count, _)| format!("{} {}", state, count)).collect();
+            egui::CollapsingHeader::new(format!("1. Split: {} rows", counts.join(", "))).id_source("pipeline_preview_split").show(ui, |ui| {
+                for (state, count, preview) in &outputs.split {
+                    ui.label(RichText::new(format!("{} ({} rows)", state, count)).strong());
+                    egui::ScrollArea::horizontal().id_source(("pipeline_preview_state", state)).show(ui, |ui| {
+                        egui::Grid::new(("pipeline_preview_rows", state)).striped(true).show(ui, |ui| {
+                            for record in preview {
+                                for field in record {
+                                    ui.label(field);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                    });
+                }
+            });
+        }
+        if let Some(extracted) = &outputs.extracted {
+            if let Some(message) = email_preview(ui, "pipeline_preview_extracted", format!("2. Extracted {} emails", extracted.len()), extracted) {
+                self.status.set(message);
+            }
+        }
+        if let Some(remainder) = &outputs.remainder {
+            if let Some(message) = email_preview(ui, "pipeline_preview_remainder", format!("3. {} emails not suppressed", remainder.len()), remainder) {
+                self.status.set(message);
+            }
+        }
+        if let Some(search) = &outputs.search {
+            let matches = search.matches.lock().unwrap();
+            egui::CollapsingHeader::new(format!("4. Search: {} matches", matches.len())).id_source("pipeline_preview_search").show(ui, |ui| {
+                if let Some(message) = self.results_table.ui(ui, &matches) {
+                    self.status.set(message);
+                }
+            });
+        }
+    }
+
+    // Runs a pipeline from the history again with the same settings
+    pub fn rerun(&mut self, job: &JobRecord, tx: &Sender<Event>) {
+        let JobSettings::Pipeline { settings } = &job.settings else {
+            return;
+        };
+        if self.running {
+            self.status.set("The pipeline is already running");
+            return;
+        }
+        self.settings = (**settings).clone();
+        match self.start(tx) {
+            Ok(()) => self.status.set("Pipeline running..."),
+            Err(message) => self.status.set(message),
+        }
+    }
+
+    fn start(&mut self, tx: &Sender<Event>) -> Result<(), String> {
+        let settings = self.settings.clone();
+        if settings.inputs.is_empty() {
+            return Err("Please select the CSV files to split first".to_string());
+        }
+        if !split_list(&settings.states).contains(&settings.extract_state) {
+            return Err("Please choose which state's emails to extract".to_string());
+        }
+        let events = EventSender::new(TabId::Pipeline, tx);
+        self.progress = Arc::new(Progress::new());
+        self.progress.start(&settings.inputs);
+        self.outputs = Arc::new(Mutex::new(StepOutputs::default()));
+        self.results_table.reset();
+        self.running = true;
+        self.control = Arc::new(JobControl::new());
+        let (progress, outputs, control) = (self.progress.clone(), self.outputs.clone(), self.control.clone());
+        thread::spawn(move || {
+            let _finished = control.finish_on_drop();
+            if panic::catch_unwind(AssertUnwindSafe(|| run_chain(settings, &events, &progress, &outputs))).is_err() {
+                progress.finish();
+                if let Some(search) = outputs.lock().ok().as_ref().and_then(|outputs| outputs.search.as_ref()) {
+                    search.progress.finish();
+                }
+                events.error("The pipeline stopped because of an internal error");
+            }
+        });
+        Ok(())
+    }
+}
+
+// Shows the first emails of a step, with a button to save them all. Returns a status message.
+fn email_preview(ui: &mut egui::Ui, id: &str, title: String, emails: &[String]) -> Option<String> {
+    let mut status = None;
+    egui::CollapsingHeader::new(title).id_source(id).show(ui, |ui| {
+        for email in emails.iter().take(PREVIEW_ROWS) {
+            ui.label(email);
+        }
+        if emails.len() > PREVIEW_ROWS {
+            ui.label(format!("... and {} more", emails.len() - PREVIEW_ROWS));
+        }
+        if ui.button("💾 Save list").clicked() {
+            if let Some(path) = FileDialog::new().add_filter("Text file", &["txt"]).save_file() {
+                status = Some(match save_emails(&path, emails) {
+                    Ok(()) => format!("Saved {} emails to {}", emails.len(), path.display()),
+                    Err(e) => format!("Error saving {}: {}", path.display(), e),
+                });
+            }
+        }
+    });
+    status
+}
+
+fn save_emails(path: &Path, emails: &[String]) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    for email in emails {
+        writeln!(file, "{}", email)?;
+    }
+    Ok(())
+}
+
+// Runs the steps in order. The search reports the end of the whole pipeline; when there is no
+// search, or a step cannot run, the pipeline reports it itself.
+fn run_chain(settings: ChainSettings, events: &EventSender, progress: &Progress, outputs: &Mutex<StepOutputs>) {
+    let started = Instant::now();
+    events.started(format!("Running the pipeline on {} files...", settings.inputs.len()));
+    let mut inputs: Vec<InputFile> = settings.inputs.iter().map(|path| InputFile::hash(path)).collect();
+    inputs.extend(settings.suppression_list.iter().map(|path| InputFile::hash(path)));
+    let mut errors = 0;
+    // The files the steps have written, for the history
+    let mut written: Vec<OutputFile> = Vec::new();
+    let job_settings = JobSettings::Pipeline { settings: Box::new(settings.clone()) };
+    let finish = |message: String, errors: usize, inputs: Vec<InputFile>, outputs: Vec<OutputFile>| {
+        events.finished(Summary {
+            message,
+            files: settings.inputs.len(),
+            errors,
+            elapsed: started.elapsed(),
+            details: JobDetails { settings: job_settings.clone(), inputs, outputs, rows: progress.snapshot().rows },
+        });
+    };
+
+    // 1. Split by state, keeping every row of the state to extract
+    let states = split_list(&settings.states);
+    let email_domains = split_list(&settings.email_domains);
+    let extract_index = states.iter().position(|state| *state == settings.extract_state);
+    let mut split: Vec<(String, usize, Vec<StringRecord>)> = states.iter().map(|state| (state.clone(), 0, Vec::new())).collect();
+    let mut selected_rows = Vec::new();
+    let mut reading = CsvReading::new(settings.encoding, settings.dialect.header_if_chosen(), settings.lenient, settings.bad_records_file.as_deref(), events);
+    for file in &settings.inputs {
+        progress.set_current_file(&file.display().to_string());
+        let result = crate::split_by_state(file, &states, &email_domains, &mut reading, progress, &mut |index, record| {
+            let (_, count, preview) = &mut split[index];
+            *count += 1;
+            if preview.len() < PREVIEW_ROWS {
+                preview.push(record.clone());
+            }
+            if Some(index) == extract_index {
+                selected_rows.push(record.clone());
+            }
+            Ok(())
+        });
+        match result {
+            Ok(_) => events.file_done(file.display().to_string(), ""),
+            Err(e) => {
+                errors += 1;
+                events.file_error(&file.display().to_string(), format!("Error processing {}: {}", file.display(), e));
+            }
+        }
+        progress.file_done();
+    }
+    progress.finish();
+    written.extend(reading.finish(events));
+    events.progress(format!("Step 1: {} rows for {}", selected_rows.len(), settings.extract_state));
+    outputs.lock().unwrap().split = split;
+
+    // 2. Extract the emails of those rows, once each
+    let email_regex = formats::email_regex();
+    let mut seen = HashSet::new();
+    let mut extracted = Vec::new();
+    for field in selected_rows.iter().flat_map(|record| record.iter()) {
+        for found in email_regex.find_iter(field) {
+            let email = found.as_str().to_lowercase();
+            if seen.insert(email.clone()) {
+                extracted.push(email);
+            }
+        }
+    }
+    drop(selected_rows);
+    events.progress(format!("Step 2: {} emails extracted", extracted.len()));
+    outputs.lock().unwrap().extracted = Some(extracted.clone());
+
+    // 3. Compare with the suppression list, keeping the emails that are only in the extracted ones
+    let remainder: Vec<String> = match &settings.suppression_list {
+        Some(path) => match email_comparison::read_emails(path, settings.encoding) {
+            Ok(suppressed) => email_comparison::compare_emails(&extracted.into_iter().collect(), &suppressed).0,
+            Err(e) => {
+                events.error(format!("Could not read the suppression list {}: {}", path.display(), e));
+                return finish("Pipeline stopped at step 3".to_string(), errors + 1, inputs, written);
+            }
+        },
+        None => extracted,
+    };
+    events.progress(format!("Step 3: {} emails remain", remainder.len()));
+    outputs.lock().unwrap().remainder = Some(remainder.clone());
+    if let Some(path) = &settings.remainder_file {
+        match save_emails(path, &remainder) {
+            Ok(()) => written.push(OutputFile { path: path.clone(), rows: remainder.len() as u64 }),
+            Err(e) => {
+                errors += 1;
+                events.error(format!("Error saving {}: {}", path.display(), e));
+            }
+        }
+    }
+
+    // 4. Search the folder for what remains
+    let Some(folder) = &settings.archive_folder else {
+        return finish(format!("Pipeline finished. {} emails remain.", remainder.len()), errors, inputs, written);
+    };
+    let preset = EmailSearchPreset {
+        folder: Some(folder.clone()),
+        email_list: None,
+        results_file: settings.results_file.clone(),
+        settings: EmailSearchSettings { query_mode: QueryMode::EmailList, ..settings.search.clone() },
+    };
+    match SearchJob::new(&preset, Some(&remainder), None, None, events.clone()) {
+        Ok(job) => {
+            let job = job.record_as(job_settings.clone(), inputs, written);
+            outputs.lock().unwrap().search = Some(SearchRun { progress: job.progress(), control: job.control(), matches: job.matches() });
+            job.run();
+        }
+        Err(message) => {
+            events.error(message);
+            finish("Pipeline stopped at step 4".to_string(), errors + 1, inputs, written);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use eframe::egui;
+use egui::{Color32, RichText};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Every tab's presets, one section per tab, in the app's data folder
+pub const PRESETS_FILE: &str = "presets.json";
+
+fn read_presets_file(path: &Path) -> Result<Map<String, Value>, Box<dyn std::error::Error>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(serde_json::from_str(&text)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Map::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Named settings of one tab, with a dropdown to load them and buttons to save and delete them
+pub struct PresetPicker {
+    path: PathBuf,
+    section: &'static str,
+    presets: BTreeMap<String, Value>,
+    name: String,
+    // Message and whether it is an error
+    status: Option<(String, bool)>,
+}
+
+impl PresetPicker {
+    pub fn new(section: &'static str) -> Self {
+        let mut picker = Self {
+            path: crate::app_data_dir().join(PRESETS_FILE),
+            section,
+            presets: BTreeMap::new(),
+            name: String::new(),
+            status: None,
+        };
+        picker.load();
+        picker
+    }
+
+    fn load(&mut self) {
+        match read_presets_file(&self.path) {
+            Ok(mut sections) => {
+                if let Some(Value::Object(presets)) = sections.remove(self.section) {
+                    self.presets = presets.into_iter().collect();
+                }
+            }
+            Err(e) => self.status = Some((format!("Could not read {}: {}", self.path.display(), e), true)),
+        }
+    }
+
+    // Re-reads the file so the other tabs' sections are written back unchanged
+    fn write(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut sections = read_presets_file(&self.path)?;
+        let presets: Map<String, Value> = self.presets.clone().into_iter().collect();
+        sections.insert(self.section.to_string(), Value::Object(presets));
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp_path = self.path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_string_pretty(&sections)?)?;
+        fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+
+    fn save_status(&mut self, result: Result<(), Box<dyn std::error::Error>>, done: String) {
+        self.status = Some(match result {
+            Ok(()) => (done, false),
+            Err(e) => (format!("Could not write {}: {}", self.path.display(), e), true),
+        });
+    }
+
+    // Returns true when a preset was loaded into `current`
+    pub fn ui<T: Serialize + DeserializeOwned>(&mut self, ui: &mut egui::Ui, current: &mut T) -> bool {
+        let mut loaded = false;
+        ui.horizontal(|ui| {
+            ui.label("Preset:");
+            let mut picked = None;
+            egui::ComboBox::from_id_source(("preset", self.section))
+                .selected_text(if self.name.is_empty() { "Choose..." } else { self.name.as_str() })
+                .show_ui(ui, |ui| {
+                    for name in self.presets.keys() {
+                        if ui.selectable_label(*name == self.name, name).clicked() {
+                            picked = Some(name.clone());
+                        }
+                    }
+                });
+            if let Some(name) = picked {
+                match serde_json::from_value(self.presets[&name].clone()) {
+                    Ok(settings) => {
+                        *current = settings;
+                        loaded = true;
+                        self.status = None;
+                    }
+                    Err(e) => self.status = Some((format!("Preset '{}' could not be loaded: {}", name, e), true)),
+                }
+                self.name = name;
+            }
+
+            ui.add(egui::TextEdit::singleline(&mut self.name).hint_text("Preset name").desired_width(120.0));
+            let name = self.name.trim().to_string();
+            if ui.add_enabled(!name.is_empty(), egui::Button::new("💾 Save preset")).clicked() {
+                match serde_json::to_value(&*current) {
+                    Ok(value) => {
+                        self.presets.insert(name.clone(), value);
+                        let result = self.write();
+                        self.save_status(result, format!("Saved preset '{}'", name));
+                    }
+                    Err(e) => self.status = Some((format!("Preset could not be saved: {}", e), true)),
+                }
+            }
+            if ui.add_enabled(self.presets.contains_key(&name), egui::Button::new("🗑 Delete")).clicked() {
+                self.presets.remove(&name);
+                let result = self.write();
+                self.save_status(result, format!("Deleted preset '{}'", name));
+                self.name.clear();
+            }
+        });
+        if let Some((status, is_error)) = &self.status {
+            let color = if *is_error { Color32::RED } else { ui.visuals().text_color() };
+            ui.label(RichText::new(status).color(color));
+        }
+        loaded
+    }
+}
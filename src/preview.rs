@@ -0,0 +1,165 @@
+use csv::StringRecord;
+use eframe::egui;
+use egui::{Color32, RichText};
+use std::error::Error;
+use std::io::Read;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use crate::archive;
+use crate::dialect::{self, ColumnType, Dialect, DialectOptions};
+use crate::encoding::{self, InputEncoding};
+use crate::formats;
+use crate::progress::Progress;
+
+// Records shown for each file
+const PREVIEW_RECORDS: usize = 10;
+// Selected files, and CSV files inside an archive, that are previewed; the rest are only counted
+const PREVIEW_FILES: usize = 5;
+// Previews kept so going back to earlier files, or settings, shows them again at once
+const CACHED_PREVIEWS: usize = 20;
+
+pub struct FilePreview {
+    pub name: String,
+    pub encoding: InputEncoding,
+    pub dialect: Dialect,
+    pub header: Option<StringRecord>,
+    pub records: Vec<StringRecord>,
+    pub column_types: Vec<ColumnType>,
+}
+
+fn preview_reader(name: &str, reader: &mut dyn Read, encoding: InputEncoding, options: &DialectOptions) -> Result<FilePreview, Box<dyn Error>> {
+    let (input, used_encoding) = encoding::decode_reader(reader, encoding)?;
+    let (input, dialect) = dialect::detect(input, options)?;
+    let mut builder = dialect.reader_builder();
+    builder.has_headers(false).flexible(true);
+    let mut records = Vec::new();
+    for result in builder.from_reader(input).records().take(PREVIEW_RECORDS + 1) {
+        records.push(result?);
+    }
+    let header = if dialect.has_header && !records.is_empty() { Some(records.remove(0)) } else { None };
+    records.truncate(PREVIEW_RECORDS);
+    let column_types = dialect::column_types(&records, &formats::email_regex());
+    Ok(FilePreview { name: name.to_string(), encoding: used_encoding, dialect, header, records, column_types })
+}
+
+// Reads the first records of `path`, or of the first CSV files inside it when it is an archive
+pub fn preview_file(path: &Path, encoding: InputEncoding, options: &DialectOptions) -> Result<Vec<FilePreview>, Box<dyn Error>> {
+    let mut previews = Vec::new();
+    archive::visit_file(path, &Progress::new(), &|name| archive::has_extension(name, &["csv"]), &mut |name, reader| {
+        previews.push(preview_reader(name, reader, encoding, options)?);
+        // Stops going through a large archive once there is enough to show
+        Ok(if previews.len() == PREVIEW_FILES { ControlFlow::Break(()) } else { ControlFlow::Continue(()) })
+    })?;
+    Ok(previews)
+}
+
+type PreviewResult = Result<Vec<FilePreview>, String>;
+
+// A file's preview with the encoding and dialect options it is read with; `None` while it is read
+struct CachedPreview {
+    path: PathBuf,
+    encoding: InputEncoding,
+    options: DialectOptions,
+    result: Arc<Mutex<Option<PreviewResult>>>,
+}
+
+// The first records of a tab's selected files, read on a worker thread when the files, the
+// encoding or the dialect change
+#[derive(Default)]
+pub struct PreviewPane {
+    cache: Vec<CachedPreview>,
+}
+
+impl PreviewPane {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, id: &str, files: &[PathBuf], encoding: InputEncoding, options: &DialectOptions) {
+        egui::CollapsingHeader::new("🔎 Preview").id_source(id).show(ui, |ui| {
+            if files.is_empty() {
+                ui.label("Select files to see their first records");
+                return;
+            }
+            // Only read while the pane is open
+            let results: Vec<_> = files.iter().take(PREVIEW_FILES).map(|file| (file, self.preview(file, encoding, options))).collect();
+            let mut reading = false;
+            egui::ScrollArea::both().id_source(id).max_height(300.0).show(ui, |ui| {
+                for (path, result) in &results {
+                    match &*result.lock().unwrap() {
+                        None => {
+                            reading = true;
+                            ui.horizontal(|ui| {
+                                ui.spinner();
+                                ui.label(format!("Reading {}...", path.display()));
+                            });
+                        }
+                        Some(Ok(previews)) if previews.is_empty() => {
+                            ui.label(format!("{}: no CSV files", path.display()));
+                        }
+                        Some(Ok(previews)) => {
+                            for preview in previews {
+                                preview_ui(ui, preview);
+                            }
+                        }
+                        Some(Err(e)) => {
+                            ui.label(RichText::new(format!("{}: {}", path.display(), e)).color(Color32::RED));
+                        }
+                    }
+                }
+                if files.len() > PREVIEW_FILES {
+                    ui.label(format!("... and {} more files", files.len() - PREVIEW_FILES));
+                }
+            });
+            if reading {
+                ui.ctx().request_repaint_after(Duration::from_millis(100));
+            }
+        });
+    }
+
+    // The cached preview of `path`, starting to read it when there is none for these settings
+    fn preview(&mut self, path: &Path, encoding: InputEncoding, options: &DialectOptions) -> Arc<Mutex<Option<PreviewResult>>> {
+        let cached = self.cache.iter().find(|cached| cached.path == path && cached.encoding == encoding && cached.options == *options);
+        if let Some(cached) = cached {
+            return cached.result.clone();
+        }
+        if self.cache.len() == CACHED_PREVIEWS {
+            self.cache.remove(0);
+        }
+        let result = Arc::new(Mutex::new(None));
+        let (shared, path, options) = (result.clone(), path.to_path_buf(), *options);
+        self.cache.push(CachedPreview { path: path.clone(), encoding, options, result: result.clone() });
+        thread::spawn(move || {
+            let preview = preview_file(&path, encoding, &options).map_err(|e| e.to_string());
+            *shared.lock().unwrap() = Some(preview);
+        });
+        result
+    }
+}
+
+fn preview_ui(ui: &mut egui::Ui, preview: &FilePreview) {
+    ui.label(RichText::new(&preview.name).strong());
+    ui.label(format!("Encoding: {}   Dialect: {}", preview.encoding.label(), preview.dialect.describe()));
+    egui::Grid::new(("preview", &preview.name)).striped(true).show(ui, |ui| {
+        for column in &preview.column_types {
+            ui.label(RichText::new(column.label()).italics());
+        }
+        ui.end_row();
+        if let Some(header) = &preview.header {
+            for field in header {
+                ui.label(RichText::new(field).strong());
+            }
+            ui.end_row();
+        }
+        for record in &preview.records {
+            for field in record {
+                ui.label(field);
+            }
+            ui.end_row();
+        }
+    });
+    ui.add_space(10.0);
+}
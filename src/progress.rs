@@ -0,0 +1,221 @@
+use eframe::egui;
+use egui::RichText;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Counters a job's workers update while the UI reads them. Bytes are the raw bytes read from
+// the input files, so they line up with the file sizes the total is made of.
+#[derive(Default)]
+pub struct Progress {
+    running: AtomicBool,
+    files_total: AtomicUsize,
+    files_done: AtomicUsize,
+    bytes_total: AtomicU64,
+    bytes_done: AtomicU64,
+    // Part of `bytes_done` that was not read, e.g. files searched before a resume
+    bytes_skipped: AtomicU64,
+    rows: AtomicU64,
+    started: Mutex<Option<Instant>>,
+    current_file: Mutex<String>,
+}
+
+// A consistent-enough copy of the counters for display
+pub struct ProgressSnapshot {
+    pub files_total: usize,
+    pub files_done: usize,
+    pub bytes_total: u64,
+    pub bytes_done: u64,
+    pub bytes_skipped: u64,
+    pub rows: u64,
+    pub elapsed: Duration,
+    pub current_file: String,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Resets the counters for a job over `files`
+    pub fn start(&self, files: &[impl AsRef<Path>]) {
+        let bytes: u64 = files.iter().filter_map(|path| path.as_ref().metadata().ok()).map(|m| m.len()).sum();
+        self.files_total.store(files.len(), Ordering::Relaxed);
+        self.files_done.store(0, Ordering::Relaxed);
+        self.bytes_total.store(bytes, Ordering::Relaxed);
+        self.bytes_done.store(0, Ordering::Relaxed);
+        self.bytes_skipped.store(0, Ordering::Relaxed);
+        self.rows.store(0, Ordering::Relaxed);
+        *self.started.lock().unwrap() = Some(Instant::now());
+        self.current_file.lock().unwrap().clear();
+        self.running.store(true, Ordering::SeqCst);
+    }
+
+    pub fn finish(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    pub fn set_current_file(&self, name: &str) {
+        *self.current_file.lock().unwrap() = name.to_string();
+    }
+
+    // Counts a file as done without reading it, e.g. one finished before a search was resumed
+    pub fn skip_file(&self, path: &Path) {
+        let bytes = path.metadata().map(|m| m.len()).unwrap_or(0);
+        self.bytes_skipped.fetch_add(bytes, Ordering::Relaxed);
+        self.add_bytes(bytes);
+        self.file_done();
+    }
+
+    pub fn file_done(&self) {
+        self.files_done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        self.bytes_done.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn add_rows(&self, rows: u64) {
+        self.rows.fetch_add(rows, Ordering::Relaxed);
+    }
+
+    // Wraps `inner` so the bytes read through it count towards the progress
+    pub fn reader<R>(&self, inner: R) -> CountingReader<'_, R> {
+        CountingReader { inner, progress: self }
+    }
+
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        ProgressSnapshot {
+            files_total: self.files_total.load(Ordering::Relaxed),
+            files_done: self.files_done.load(Ordering::Relaxed),
+            bytes_total: self.bytes_total.load(Ordering::Relaxed),
+            // Archives can be read past their end while seeking, so never report more than the total
+            bytes_done: self.bytes_done.load(Ordering::Relaxed).min(self.bytes_total.load(Ordering::Relaxed)),
+            bytes_skipped: self.bytes_skipped.load(Ordering::Relaxed),
+            rows: self.rows.load(Ordering::Relaxed),
+            elapsed: self.started.lock().unwrap().map(|started| started.elapsed()).unwrap_or_default(),
+            current_file: self.current_file.lock().unwrap().clone(),
+        }
+    }
+}
+
+impl ProgressSnapshot {
+    pub fn fraction(&self) -> f32 {
+        if self.bytes_total > 0 {
+            self.bytes_done as f32 / self.bytes_total as f32
+        } else {
+            self.files_done as f32 / self.files_total.max(1) as f32
+        }
+    }
+
+    pub fn rows_per_second(&self) -> f64 {
+        self.rows as f64 / self.elapsed.as_secs_f64().max(0.001)
+    }
+
+    // Skipped bytes took no time, so they are left out of the rate
+    fn bytes_read(&self) -> u64 {
+        self.bytes_done.saturating_sub(self.bytes_skipped)
+    }
+
+    pub fn bytes_per_second(&self) -> f64 {
+        self.bytes_read() as f64 / self.elapsed.as_secs_f64().max(0.001)
+    }
+
+    // Extrapolated from the throughput so far; None until there is enough to go on
+    pub fn eta(&self) -> Option<Duration> {
+        if self.elapsed < Duration::from_secs(1) {
+            return None;
+        }
+        if self.bytes_total > 0 {
+            let read = self.bytes_read();
+            if read == 0 {
+                return None;
+            }
+            let remaining = self.bytes_total.saturating_sub(self.bytes_done);
+            return Some(Duration::from_secs_f64(self.elapsed.as_secs_f64() * remaining as f64 / read as f64));
+        }
+        let fraction = self.fraction() as f64;
+        if fraction <= 0.0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64(self.elapsed.as_secs_f64() * (1.0 - fraction) / fraction))
+    }
+}
+
+pub struct CountingReader<'a, R> {
+    inner: R,
+    progress: &'a Progress,
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.progress.add_bytes(n as u64);
+        Ok(n)
+    }
+}
+
+// Zip archives seek to their central directory, so the reader has to stay seekable
+impl<R: Seek> Seek for CountingReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} {}", value as u64, UNITS[0]) } else { format!("{:.1} {}", value, UNITS[unit]) }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60)
+    } else {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    }
+}
+
+// Progress bar with file, byte and throughput figures, shared by every tab that runs a job
+pub fn progress_panel(ui: &mut egui::Ui, progress: &Progress) {
+    if !progress.is_running() {
+        return;
+    }
+    let snapshot = progress.snapshot();
+    let percent = snapshot.fraction() * 100.0;
+    ui.add(egui::ProgressBar::new(snapshot.fraction()).text(format!("{:.1}%", percent)));
+    egui::Grid::new("progress_panel").num_columns(4).show(ui, |ui| {
+        ui.label(RichText::new("Files:").strong());
+        ui.label(format!("{}/{}", snapshot.files_done, snapshot.files_total));
+        ui.label(RichText::new("Data:").strong());
+        ui.label(format!("{} of {}", format_bytes(snapshot.bytes_done as f64), format_bytes(snapshot.bytes_total as f64)));
+        ui.end_row();
+        ui.label(RichText::new("Rows/s:").strong());
+        ui.label(format!("{:.0} ({} rows)", snapshot.rows_per_second(), snapshot.rows));
+        ui.label(RichText::new("Speed:").strong());
+        ui.label(format!("{}/s", format_bytes(snapshot.bytes_per_second())));
+        ui.end_row();
+        ui.label(RichText::new("Elapsed:").strong());
+        ui.label(format_duration(snapshot.elapsed));
+        ui.label(RichText::new("Remaining:").strong());
+        ui.label(snapshot.eta().map(format_duration).unwrap_or_else(|| "estimating...".to_string()));
+        ui.end_row();
+    });
+    if !snapshot.current_file.is_empty() {
+        ui.label(format!("Current file: {}", snapshot.current_file));
+    }
+    // Keep the figures moving even when no messages arrive
+    ui.ctx().request_repaint_after(Duration::from_millis(250));
+}
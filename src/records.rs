@@ -0,0 +1,333 @@
+use csv::{ByteRecord, Reader, StringRecord, Writer};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+use crate::dialect::{self, Dialect, DialectOptions, Escape};
+use crate::encoding::{self, InputEncoding};
+use crate::events::EventSender;
+use crate::history::OutputFile;
+
+// Called with every record read
+pub type RecordVisitor<'a> = dyn FnMut(&StringRecord) -> Result<(), Box<dyn Error>> + 'a;
+
+// Written in the output folder of a lenient job
+pub const BAD_RECORDS_FILE: &str = "bad_records.csv";
+
+// Where a lenient job writing `output_file` lists its bad records: `<name>_bad_records.csv` next to it
+pub fn bad_records_path(output_file: &Path) -> PathBuf {
+    let stem = output_file.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    output_file.with_file_name(format!("{}_{}", stem, BAD_RECORDS_FILE))
+}
+
+// Rows a lenient job could not read as they were. Without a file they are only counted.
+pub struct BadRecords {
+    writer: Option<(Writer<File>, PathBuf)>,
+    count: u64,
+}
+
+impl BadRecords {
+    pub fn create(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut writer = Writer::from_path(path)?;
+        writer.write_record(["file", "line", "action", "error", "record"])?;
+        Ok(Self { writer: Some((writer, path.to_path_buf())), count: 0 })
+    }
+
+    pub fn counting() -> Self {
+        Self { writer: None, count: 0 }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    fn add(&mut self, file: &str, line: u64, action: &str, error: &str, record: &str) -> csv::Result<()> {
+        self.count += 1;
+        match &mut self.writer {
+            Some((writer, _)) => writer.write_record([file, &line.to_string(), action, error, record]),
+            None => Ok(()),
+        }
+    }
+}
+
+// How the CSV inputs of a job are read
+pub struct CsvReading {
+    pub encoding: InputEncoding,
+    pub dialect: DialectOptions,
+    // Set in lenient mode: rows with a different number of fields are kept, invalid text is
+    // repaired and lines opening a quote that is never closed are skipped, and each is reported here
+    pub bad_records: Option<BadRecords>,
+    // The header of the input last read, when it had one
+    pub header: Option<StringRecord>,
+}
+
+impl CsvReading {
+    // A lenient job writes its bad records to `bad_records_path`, or only counts them when there
+    // is none or the file cannot be created
+    pub fn new(encoding: InputEncoding, dialect: DialectOptions, lenient: bool, bad_records_path: Option<&Path>, events: &EventSender) -> Self {
+        let bad_records = lenient.then(|| match bad_records_path {
+            Some(path) => BadRecords::create(path).unwrap_or_else(|e| {
+                events.error(format!("Could not create {}: {}", path.display(), e));
+                BadRecords::counting()
+            }),
+            None => BadRecords::counting(),
+        });
+        Self { encoding, dialect, bad_records, header: None }
+    }
+
+    // Reads `reader`, the input named `name`, calling `on_record` for every record. Returns the
+    // encoding and dialect that were used.
+    pub fn read(
+        &mut self,
+        name: &str,
+        reader: &mut dyn Read,
+        on_record: &mut RecordVisitor,
+    ) -> Result<(InputEncoding, Dialect), Box<dyn Error>> {
+        let (input, used_encoding) = encoding::decode_reader(reader, self.encoding)?;
+        let (input, used_dialect) = dialect::detect(input, &self.dialect)?;
+        let mut builder = used_dialect.reader_builder();
+        let Some(bad_records) = &mut self.bad_records else {
+            let mut rdr = builder.from_reader(input);
+            self.header = read_header(&mut rdr, &used_dialect)?;
+            for result in rdr.records() {
+                on_record(&result?)?;
+            }
+            return Ok((used_encoding, used_dialect));
+        };
+
+        builder.flexible(true);
+        let mut rdr = builder.from_reader(Replay::new(input));
+        self.header = read_header(&mut rdr, &used_dialect)?;
+        let mut expected = self.header.as_ref().map(StringRecord::len);
+        let delimiter = (used_dialect.delimiter as char).to_string();
+        let mut bytes = ByteRecord::new();
+        // Lines before the input of the current reader, which starts again after a skipped line
+        let mut skipped_lines = 0;
+        while rdr.read_byte_record(&mut bytes)? {
+            let position = bytes.position().cloned().unwrap_or_else(csv::Position::new);
+            let line = position.line() + skipped_lines;
+            if stray_quote(rdr.get_ref().record(position.byte(), rdr.position().byte()), &used_dialect) {
+                // Skip the line with the stray quote and read the lines it swallowed again
+                let replay = rdr.into_inner();
+                let (text, rest) = replay.split_first_line(position.byte());
+                bad_records.add(name, line, "skipped", "unbalanced quote", &text)?;
+                builder.has_headers(false);
+                rdr = builder.from_reader(Replay::new(Box::new(Cursor::new(rest).chain(replay.inner))));
+                skipped_lines = line;
+                continue;
+            }
+            let end = rdr.position().byte();
+            rdr.get_mut().forget_before(end);
+            let record = match StringRecord::from_byte_record(bytes.clone()) {
+                Ok(record) => record,
+                Err(e) => {
+                    let record = StringRecord::from_byte_record_lossy(bytes.clone());
+                    let text = record.iter().collect::<Vec<_>>().join(&delimiter);
+                    bad_records.add(name, line, "repaired", &format!("{}; invalid characters replaced", e), &text)?;
+                    record
+                }
+            };
+            let expected = *expected.get_or_insert(record.len());
+            if record.len() != expected {
+                let text = record.iter().collect::<Vec<_>>().join(&delimiter);
+                bad_records.add(name, line, "kept", &format!("expected {} fields, found {}", expected, record.len()), &text)?;
+            }
+            on_record(&record)?;
+        }
+        Ok((used_encoding, used_dialect))
+    }
+
+    // Closes the bad-records file and warns when there were any. Returns the file as a job output.
+    pub fn finish(self, events: &EventSender) -> Option<OutputFile> {
+        let bad_records = self.bad_records?;
+        if bad_records.count > 0 {
+            match &bad_records.writer {
+                Some((_, path)) => events.warning(format!("{} bad records, listed in {}", bad_records.count, path.display())),
+                None => events.warning(format!("{} bad records", bad_records.count)),
+            }
+        }
+        let (mut writer, path) = bad_records.writer?;
+        if let Err(e) = writer.flush() {
+            events.error(format!("Error writing {}: {}", path.display(), e));
+        }
+        Some(OutputFile { path, rows: bad_records.count })
+    }
+}
+
+fn read_header<R: Read>(rdr: &mut Reader<R>, dialect: &Dialect) -> csv::Result<Option<StringRecord>> {
+    Ok(if dialect.has_header { Some(StringRecord::from_byte_record_lossy(rdr.byte_headers()?.clone())) } else { None })
+}
+
+// Whether `raw` spans several lines because of a quote that is never closed, or is closed in the
+// middle of a field. The parser accepts both, running on into the lines after the first.
+fn stray_quote(raw: &[u8], dialect: &Dialect) -> bool {
+    let end = raw.iter().rposition(|&b| b != b'\n' && b != b'\r').map_or(0, |last| last + 1);
+    let raw = &raw[..end];
+    if !raw.iter().any(|&b| b == b'\n' || b == b'\r') {
+        return false;
+    }
+    let (mut in_quotes, mut field_start) = (false, true);
+    let mut i = 0;
+    while i < raw.len() {
+        let b = raw[i];
+        if in_quotes {
+            let escaped = match dialect.escape {
+                Escape::Backslash => b == b'\\',
+                Escape::Doubled => b == dialect.quote && raw.get(i + 1) == Some(&dialect.quote),
+            };
+            if escaped {
+                // Skip the escaped character too
+                i += 1;
+            } else if b == dialect.quote {
+                in_quotes = false;
+                if !matches!(raw.get(i + 1), None | Some(b'\n' | b'\r')) && raw.get(i + 1) != Some(&dialect.delimiter) {
+                    return true;
+                }
+            }
+        } else if b == dialect.quote && field_start {
+            in_quotes = true;
+        }
+        field_start = !in_quotes && (b == dialect.delimiter || b == b'\n' || b == b'\r');
+        i += 1;
+    }
+    in_quotes
+}
+
+// Keeps what is read through it, from byte `start` of the input on, so that a malformed record
+// can be read again from the line after its first one
+struct Replay<'a> {
+    inner: Box<dyn Read + 'a>,
+    kept: Vec<u8>,
+    start: u64,
+}
+
+impl<'a> Replay<'a> {
+    fn new(inner: Box<dyn Read + 'a>) -> Self {
+        Self { inner, kept: Vec::new(), start: 0 }
+    }
+
+    // Drops the bytes before `offset` once enough of them have piled up
+    fn forget_before(&mut self, offset: u64) {
+        let forget = (offset - self.start) as usize;
+        if forget >= 64 * 1024 {
+            self.kept.drain(..forget);
+            self.start = offset;
+        }
+    }
+
+    // The bytes read between the input offsets `start` and `end`
+    fn record(&self, start: u64, end: u64) -> &[u8] {
+        &self.kept[(start - self.start) as usize..(end - self.start) as usize]
+    }
+
+    // Splits what was read from `offset` on into the first line, without its line break, and the rest
+    fn split_first_line(&self, offset: u64) -> (String, Vec<u8>) {
+        let kept = &self.kept[(offset - self.start) as usize..];
+        let end = kept.iter().position(|&b| b == b'\n' || b == b'\r').unwrap_or(kept.len());
+        let next = match kept[end..] {
+            [b'\r', b'\n', ..] => end + 2,
+            [_, ..] => end + 1,
+            [] => end,
+        };
+        (String::from_utf8_lossy(&kept[..end]).into_owned(), kept[next..].to_vec())
+    }
+}
+
+impl Read for Replay<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.kept.extend_from_slice(&buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: DialectOptions = DialectOptions { delimiter: Some(','), quote: Some('"'), escape: Some(Escape::Doubled), has_header: Some(true) };
+
+    // The records read from `input` and the bad records listed for it
+    fn read_lenient(test: &str, input: &str) -> (Vec<Vec<String>>, Vec<Vec<String>>) {
+        let path = std::env::temp_dir().join(format!("csv_processor_{}_{}.csv", test, std::process::id()));
+        let mut reading = CsvReading { encoding: InputEncoding::Utf8, dialect: CSV, bad_records: Some(BadRecords::create(&path).unwrap()), header: None };
+        let mut records = Vec::new();
+        reading
+            .read(test, &mut input.as_bytes(), &mut |record| {
+                records.push(record.iter().map(str::to_string).collect());
+                Ok(())
+            })
+            .unwrap();
+        let (mut writer, _) = reading.bad_records.unwrap().writer.unwrap();
+        writer.flush().unwrap();
+        let bad = csv::Reader::from_path(&path).unwrap().records().map(|record| record.unwrap().iter().map(str::to_string).collect()).collect();
+        std::fs::remove_file(&path).unwrap();
+        (records, bad)
+    }
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.iter().map(|field| field.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn strict_reading_rejects_a_short_row() {
+        let mut reading = CsvReading { encoding: InputEncoding::Utf8, dialect: CSV, bad_records: None, header: None };
+        let result = reading.read("strict", &mut "name,email\nAnn\n".as_bytes(), &mut |_| Ok(()));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn rows_with_other_field_counts_are_kept_and_listed() {
+        let (records, bad) = read_lenient("kept", "name,email\nAnn\nBob,bob@example.com,extra\n");
+        assert_eq!(records, rows(&[&["Ann"], &["Bob", "bob@example.com", "extra"]]));
+        assert_eq!(bad.len(), 2);
+        assert_eq!(bad[0][1..4], ["2", "kept", "expected 2 fields, found 1"]);
+        assert_eq!(bad[1][1..3], ["3", "kept"]);
+    }
+
+    #[test]
+    fn a_line_with_an_unclosed_quote_is_skipped() {
+        let (records, bad) = read_lenient("unclosed", "name,email\n\"Ann,ann@example.com\nBob,bob@example.com\r\nCy,cy@example.com\n");
+        assert_eq!(records, rows(&[&["Bob", "bob@example.com"], &["Cy", "cy@example.com"]]));
+        assert_eq!(bad, rows(&[&["unclosed", "2", "skipped", "unbalanced quote", "\"Ann,ann@example.com"]]));
+    }
+
+    #[test]
+    fn line_numbers_count_from_the_start_after_several_skips() {
+        let input = "name,email\nAnn,ann@example.com\n\"Bob,x\n\"Cy,y\nDee,dee@example.com\n";
+        let (records, bad) = read_lenient("several", input);
+        assert_eq!(records, rows(&[&["Ann", "ann@example.com"], &["Dee", "dee@example.com"]]));
+        let lines: Vec<&str> = bad.iter().map(|record| record[1].as_str()).collect();
+        assert_eq!(lines, ["3", "4"]);
+    }
+
+    #[test]
+    fn quoted_line_breaks_are_not_malformed() {
+        let (records, bad) = read_lenient("multiline", "name,address\nAnn,\"1 Main St\nApt 2\"\nBob,\"2 Oak Ave\"\n");
+        assert_eq!(records, rows(&[&["Ann", "1 Main St\nApt 2"], &["Bob", "2 Oak Ave"]]));
+        assert!(bad.is_empty());
+    }
+
+    #[test]
+    fn escaped_quotes_do_not_close_a_field() {
+        let doubled = Dialect { delimiter: b',', quote: b'"', escape: Escape::Doubled, has_header: false };
+        assert!(!stray_quote(b"a,\"say \"\"hi\"\"\nthere\"\n", &doubled));
+        assert!(stray_quote(b"a,\"say \"hi\nthere\n", &doubled));
+        let backslash = Dialect { escape: Escape::Backslash, ..doubled };
+        assert!(!stray_quote(b"a,\"say \\\"hi\\\"\nthere\"\n", &backslash));
+    }
+
+    #[test]
+    fn an_unclosed_quote_far_into_a_large_input_is_skipped() {
+        let mut input = String::from("id,email\n");
+        for id in 0..5000 {
+            input.push_str(&format!("{},user{}@example.com\n", id, id));
+        }
+        input.push_str("\"5000,broken@example.com\n5001,last@example.com\n");
+        let (records, bad) = read_lenient("large", &input);
+        assert_eq!(records.len(), 5001);
+        assert_eq!(records[4999], ["4999", "user4999@example.com"]);
+        assert_eq!(records[5000], ["5001", "last@example.com"]);
+        assert_eq!(bad[0][1..3], ["5002", "skipped"]);
+    }
+}
@@ -0,0 +1,288 @@
+use eframe::egui;
+use egui::RichText;
+use egui_extras::{Column, TableBuilder};
+use rfd::FileDialog;
+use std::path::Path;
+
+// One row of the search results, as written to the results file
+#[derive(Clone)]
+pub struct SearchMatch {
+    pub email: String,
+    pub file: String,
+    pub record_number: usize,
+    pub column: String,
+    pub match_type: String,
+    pub record: String,
+}
+
+impl SearchMatch {
+    pub fn fields(&self) -> [String; 6] {
+        [
+            self.email.clone(),
+            self.file.clone(),
+            self.record_number.to_string(),
+            self.column.clone(),
+            self.match_type.clone(),
+            self.record.clone(),
+        ]
+    }
+
+    // `filter` is lower-cased
+    fn contains(&self, filter: &str) -> bool {
+        [&self.email, &self.file, &self.column, &self.match_type, &self.record].iter().any(|field| field.to_lowercase().contains(filter))
+            || self.record_number.to_string().contains(filter)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SortColumn {
+    Email,
+    File,
+    Row,
+    Column,
+    MatchType,
+    Preview,
+}
+
+const PREVIEW_CHARS: usize = 80;
+
+pub struct ResultsTable {
+    filter: String,
+    sort_column: SortColumn,
+    ascending: bool,
+    selected: Option<SearchMatch>,
+    // Indices of the visible rows, and the filter, sort and match count they were worked out for
+    rows: Vec<usize>,
+    rows_for: Option<(String, SortColumn, bool, usize)>,
+}
+
+impl ResultsTable {
+    pub fn new() -> Self {
+        Self {
+            filter: String::new(),
+            sort_column: SortColumn::Email,
+            ascending: true,
+            selected: None,
+            rows: Vec::new(),
+            rows_for: None,
+        }
+    }
+
+    // For a new set of matches, which may be as many as the last
+    pub fn reset(&mut self) {
+        self.rows.clear();
+        self.rows_for = None;
+        self.selected = None;
+    }
+
+    // Rows that pass the filter, in the current sort order. Matches are only ever added during a
+    // search, so the rows are worked out again only when there are more, or the filter or sort changed.
+    fn visible_rows<'a>(&mut self, matches: &'a [SearchMatch]) -> Vec<&'a SearchMatch> {
+        let key = (self.filter.clone(), self.sort_column, self.ascending, matches.len());
+        if self.rows_for.as_ref() != Some(&key) {
+            self.rows = self.sorted_rows(matches);
+            self.rows_for = Some(key);
+        }
+        self.rows.iter().map(|&i| &matches[i]).collect()
+    }
+
+    fn sorted_rows(&self, matches: &[SearchMatch]) -> Vec<usize> {
+        let filter = self.filter.to_lowercase();
+        let mut rows: Vec<usize> = (0..matches.len()).filter(|&i| filter.is_empty() || matches[i].contains(&filter)).collect();
+
+        rows.sort_by(|&a, &b| {
+            let (a, b) = (&matches[a], &matches[b]);
+            let ordering = match self.sort_column {
+                SortColumn::Email => a.email.to_lowercase().cmp(&b.email.to_lowercase()),
+                SortColumn::File => a.file.cmp(&b.file),
+                SortColumn::Row => a.record_number.cmp(&b.record_number),
+                SortColumn::Column => a.column.cmp(&b.column),
+                SortColumn::MatchType => a.match_type.cmp(&b.match_type),
+                SortColumn::Preview => a.record.cmp(&b.record),
+            };
+            // Keep ties in a stable, readable order
+            let ordering = ordering.then_with(|| a.file.cmp(&b.file)).then_with(|| a.record_number.cmp(&b.record_number));
+            if self.ascending { ordering } else { ordering.reverse() }
+        });
+        rows
+    }
+
+    // Returns a status message when an export was attempted
+    pub fn ui(&mut self, ui: &mut egui::Ui, matches: &[SearchMatch]) -> Option<String> {
+        let rows = self.visible_rows(matches);
+        let mut status = None;
+
+        ui.horizontal(|ui| {
+            ui.label("Filter:");
+            ui.add(egui::TextEdit::singleline(&mut self.filter).hint_text("email, file or record text"));
+            ui.label(format!("{} of {} matches", rows.len(), matches.len()));
+            if ui.button("📋 Copy").on_hover_text("Copy the filtered rows as CSV").clicked() {
+                let text = rows_to_csv(&rows);
+                ui.output_mut(|o| o.copied_text = text);
+            }
+            if ui.button("💾 Export").on_hover_text("Save the filtered rows to a CSV file").clicked() {
+                if let Some(path) = FileDialog::new().add_filter("CSV", &["csv"]).set_file_name("filtered_results.csv").save_file() {
+                    status = Some(match export_rows(&path, &rows) {
+                        Ok(()) => format!("Exported {} rows to {}", rows.len(), path.display()),
+                        Err(e) => format!("Error exporting results: {}", e),
+                    });
+                }
+            }
+        });
+
+        let mut clicked = None;
+        TableBuilder::new(ui)
+            .striped(true)
+            .resizable(true)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(Column::initial(180.0).at_least(80.0))
+            .column(Column::initial(220.0).at_least(80.0))
+            .column(Column::auto().at_least(40.0))
+            .column(Column::auto().at_least(40.0))
+            .column(Column::auto().at_least(60.0))
+            .column(Column::remainder().at_least(100.0))
+            .min_scrolled_height(0.0)
+            .max_scroll_height(300.0)
+            .header(20.0, |mut header| {
+                for (label, column) in [
+                    ("Email", SortColumn::Email),
+                    ("File", SortColumn::File),
+                    ("Row", SortColumn::Row),
+                    ("Column", SortColumn::Column),
+                    ("Match", SortColumn::MatchType),
+                    ("Preview", SortColumn::Preview),
+                ] {
+                    header.col(|ui| {
+                        let arrow = match (self.sort_column == column, self.ascending) {
+                            (true, true) => " ⏶",
+                            (true, false) => " ⏷",
+                            _ => "",
+                        };
+                        if ui.button(RichText::new(format!("{}{}", label, arrow)).strong()).clicked() {
+                            if self.sort_column == column {
+                                self.ascending = !self.ascending;
+                            } else {
+                                self.sort_column = column;
+                                self.ascending = true;
+                            }
+                        }
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(18.0, rows.len(), |index, mut row| {
+                    let m = rows[index];
+                    row.col(|ui| {
+                        if ui.selectable_label(false, &m.email).on_hover_text("Show details").clicked() {
+                            clicked = Some(m.clone());
+                        }
+                    });
+                    row.col(|ui| {
+                        ui.label(&m.file).on_hover_text(&m.file);
+                    });
+                    row.col(|ui| {
+                        ui.label(m.record_number.to_string());
+                    });
+                    row.col(|ui| {
+                        ui.label(&m.column);
+                    });
+                    row.col(|ui| {
+                        ui.label(&m.match_type);
+                    });
+                    row.col(|ui| {
+                        ui.label(preview(&m.record));
+                    });
+                });
+            });
+
+        if clicked.is_some() {
+            self.selected = clicked;
+        }
+        self.detail_window(ui.ctx());
+        status
+    }
+
+    fn detail_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.selected.is_some();
+        if let Some(m) = &self.selected {
+            egui::Window::new("Match details").open(&mut open).resizable(true).show(ctx, |ui| {
+                egui::Grid::new("match_details").num_columns(2).show(ui, |ui| {
+                    for (label, value) in [("Email", &m.email), ("File", &m.file), ("Column", &m.column), ("Match", &m.match_type)] {
+                        ui.label(RichText::new(label).strong());
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                    ui.label(RichText::new("Row").strong());
+                    ui.label(m.record_number.to_string());
+                    ui.end_row();
+                });
+                ui.separator();
+                egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                    ui.add(egui::TextEdit::multiline(&mut m.record.as_str()).desired_width(f32::INFINITY));
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("📋 Copy record").clicked() {
+                        ui.output_mut(|o| o.copied_text = m.record.clone());
+                    }
+                    if ui.button("📋 Copy location").clicked() {
+                        ui.output_mut(|o| o.copied_text = format!("{}:{}", m.file, m.record_number));
+                    }
+                });
+            });
+        }
+        if !open {
+            self.selected = None;
+        }
+    }
+}
+
+fn preview(record: &str) -> String {
+    let single_line = record.replace(['\n', '\r'], " ");
+    if single_line.chars().count() > PREVIEW_CHARS {
+        format!("{}…", single_line.chars().take(PREVIEW_CHARS).collect::<String>())
+    } else {
+        single_line
+    }
+}
+
+pub const RESULTS_HEADER: [&str; 6] = ["query_email", "file_path", "record_number", "matched_column", "match_type", "record"];
+
+fn rows_to_csv(rows: &[&SearchMatch]) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let _ = writer.write_record(RESULTS_HEADER);
+    for m in rows {
+        let _ = writer.write_record(m.fields());
+    }
+    String::from_utf8_lossy(&writer.into_inner().unwrap_or_default()).into_owned()
+}
+
+fn export_rows(path: &Path, rows: &[&SearchMatch]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(RESULTS_HEADER)?;
+    for m in rows {
+        writer.write_record(m.fields())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Reads matches back from a results file, e.g. when a search is resumed. Files written before
+// searches had match modes have no `match_type` column, and only hold exact matches.
+pub fn load_matches(path: &Path) -> Result<Vec<SearchMatch>, Box<dyn std::error::Error>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(path)?;
+    let mut matches = Vec::new();
+    for result in reader.records() {
+        let record = result?;
+        let field = |i: usize| record.get(i).unwrap_or_default().to_string();
+        let (match_type, record_field) = if record.len() == RESULTS_HEADER.len() - 1 { ("exact".to_string(), field(4)) } else { (field(4), field(5)) };
+        matches.push(SearchMatch {
+            email: field(0),
+            file: field(1),
+            record_number: field(2).parse().unwrap_or(0),
+            column: field(3),
+            match_type,
+            record: record_field,
+        });
+    }
+    Ok(matches)
+}
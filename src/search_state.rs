@@ -0,0 +1,49 @@
+use crate::email_search::EmailSearchSettings;
+use crate::history::InputFile;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Where an interrupted search is remembered between runs of the app
+pub const STATE_FILE: &str = "search_state.json";
+
+// Progress of a folder search, saved periodically so it can be resumed after a restart
+#[derive(Serialize, Deserialize)]
+pub struct SearchState {
+    pub folder: PathBuf,
+    pub email_list: PathBuf,
+    pub results_file: PathBuf,
+    pub settings: EmailSearchSettings,
+    // States saved before searches went file by file counted emails instead, and are resumed from
+    // the first file; matches already in the results file are not written again
+    #[serde(default)]
+    pub total_files: usize,
+    #[serde(default)]
+    pub completed_files: HashSet<PathBuf>,
+    #[serde(default)]
+    pub found_emails: HashSet<String>,
+    // The completed files as the job history records them, taken as each one finished
+    #[serde(default)]
+    pub completed_inputs: Vec<InputFile>,
+}
+
+impl SearchState {
+    // Returns None when there is no saved search or it cannot be read
+    pub fn load(path: &Path) -> Option<Self> {
+        let text = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        // Write to a temporary file first so a crash mid-write cannot corrupt the last good state
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    pub fn remove(path: &Path) {
+        let _ = fs::remove_file(path);
+    }
+}
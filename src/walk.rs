@@ -0,0 +1,154 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SymlinkPolicy {
+    Skip,
+    Follow,
+}
+
+pub struct WalkOptions {
+    include: GlobSet,
+    has_includes: bool,
+    exclude: GlobSet,
+    pub max_depth: Option<usize>,
+    pub symlinks: SymlinkPolicy,
+    pub include_hidden: bool,
+}
+
+impl WalkOptions {
+    // Patterns without a `/` also match any single file or folder name, so `backup` skips every backup folder
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, globset::Error> {
+        Ok(Self {
+            include: build_glob_set(include)?,
+            has_includes: !include.is_empty(),
+            exclude: build_glob_set(exclude)?,
+            max_depth: None,
+            symlinks: SymlinkPolicy::Skip,
+            include_hidden: false,
+        })
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    builder.build()
+}
+
+fn matches(set: &GlobSet, relative: &Path) -> bool {
+    set.is_match(relative) || relative.file_name().is_some_and(|name| set.is_match(name))
+}
+
+fn is_hidden(path: &Path, metadata: &fs::Metadata) -> bool {
+    let dot_file = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        dot_file || metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = metadata;
+        dot_file
+    }
+}
+
+// Collects every file under `root` allowed by `options`, accepted by `accept`.
+// Problems such as unreadable folders or symlink loops are returned as warnings rather than aborting the walk.
+pub fn collect_files(root: &Path, options: &WalkOptions, accept: &dyn Fn(&Path) -> bool) -> (Vec<PathBuf>, Vec<String>) {
+    let mut files = Vec::new();
+    let mut warnings = Vec::new();
+    let mut visited = HashSet::new();
+    if let Ok(canonical) = root.canonicalize() {
+        visited.insert(canonical);
+    }
+    walk_dir(root, root, 0, options, accept, &mut visited, &mut files, &mut warnings);
+    (files, warnings)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn walk_dir(
+    root: &Path,
+    dir: &Path,
+    depth: usize,
+    options: &WalkOptions,
+    accept: &dyn Fn(&Path) -> bool,
+    visited: &mut HashSet<PathBuf>,
+    files: &mut Vec<PathBuf>,
+    warnings: &mut Vec<String>,
+) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warnings.push(format!("Cannot read folder {}: {}", dir.display(), e));
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+
+        // symlink_metadata does not follow the link, so links can be told apart from what they point to
+        let link_metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warnings.push(format!("Cannot read {}: {}", path.display(), e));
+                continue;
+            }
+        };
+        if !options.include_hidden && is_hidden(&path, &link_metadata) {
+            continue;
+        }
+        if matches(&options.exclude, &relative) {
+            continue;
+        }
+
+        let is_link = link_metadata.file_type().is_symlink();
+        if is_link && options.symlinks == SymlinkPolicy::Skip {
+            continue;
+        }
+        let metadata = if is_link {
+            match fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    warnings.push(format!("Broken symlink {}: {}", path.display(), e));
+                    continue;
+                }
+            }
+        } else {
+            link_metadata
+        };
+
+        if metadata.is_dir() {
+            if options.max_depth.is_some_and(|max| depth >= max) {
+                continue;
+            }
+            // Remember every folder by its real path so a link back up the tree is not walked twice
+            let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+            if !visited.insert(canonical) {
+                if is_link {
+                    warnings.push(format!("Skipping symlink loop at {}", path.display()));
+                }
+                continue;
+            }
+            walk_dir(root, &path, depth + 1, options, accept, visited, files, warnings);
+        } else if metadata.is_file() {
+            if options.has_includes && !matches(&options.include, &relative) {
+                continue;
+            }
+            if accept(&path) {
+                files.push(path);
+            }
+        }
+    }
+}